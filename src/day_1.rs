@@ -1,73 +1,327 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+
+// Spelled-out digits, each language's words listed from zero to nine.
+const LANGUAGES: [(&str, [&str; 10]); 4] = [
+    (
+        "English",
+        [
+            "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine",
+        ],
+    ),
+    (
+        "German",
+        [
+            "null", "eins", "zwei", "drei", "vier", "fuenf", "sechs", "sieben", "acht", "neun",
+        ],
+    ),
+    (
+        "French",
+        [
+            "zero", "un", "deux", "trois", "quatre", "cinq", "six", "sept", "huit", "neuf",
+        ],
+    ),
+    (
+        "Spanish",
+        [
+            "cero", "uno", "dos", "tres", "cuatro", "cinco", "seis", "siete", "ocho", "nueve",
+        ],
+    ),
+];
+
+// The tokens that stand for digits in a calibration line.
+struct Vocabulary {
+    tokens: Vec<(String, u32)>,
+}
+
+impl Vocabulary {
+    fn from_tokens(tokens: Vec<(String, u32)>) -> Result<Vocabulary, String> {
+        let mut values: HashMap<&str, u32> = HashMap::new();
+        for (token, value) in &tokens {
+            if token.is_empty() {
+                return Err(format!("empty token for {value}"));
+            }
+            if *value > 9 {
+                return Err(format!("token {token} is {value}, not a digit"));
+            }
+            if let Some(other) = values.insert(token, *value) {
+                if other != *value {
+                    return Err(format!("token {token} is both {other} and {value}"));
+                }
+            }
+        }
+        return Ok(Vocabulary { tokens });
+    }
+
+    fn digits() -> Vocabulary {
+        return Vocabulary {
+            tokens: (0..10).map(|d| (d.to_string(), d)).collect(),
+        };
+    }
+
+    // The digits, and the words of a language for them.
+    fn digits_and_words(words: &[&str; 10]) -> Result<Vocabulary, String> {
+        let mut tokens = Vocabulary::digits().tokens;
+        for (d, word) in words.iter().enumerate() {
+            tokens.push((word.to_string(), d as u32));
+        }
+        return Vocabulary::from_tokens(tokens);
+    }
+}
+
+// A token found in a line, at char positions start..end.
+struct Match {
+    start: usize,
+    end: usize,
+    value: u32,
+}
+
+// An Aho-Corasick automaton: a trie of the patterns, where each node also links to the node of
+// its longest proper suffix in the trie, so that a scan follows all the patterns at once in a
+// single pass over the text.
+struct Automaton {
+    children: Vec<HashMap<char, usize>>,
+    suffix_link: Vec<usize>,
+    // The pattern spelled by each node, if it is one.
+    pattern_at: Vec<Option<usize>>,
+    // The nearest node along suffix links that is a pattern, to list the matches at a node.
+    pattern_link: Vec<Option<usize>>,
+}
+
+impl Automaton {
+    fn new(patterns: &Vec<Vec<char>>) -> Automaton {
+        let mut automaton = Automaton {
+            children: vec![HashMap::new()],
+            suffix_link: vec![0],
+            pattern_at: vec![None],
+            pattern_link: vec![None],
+        };
+        for (p, pattern) in patterns.iter().enumerate() {
+            let mut node = 0;
+            for c in pattern {
+                node = match automaton.children[node].get(c) {
+                    Some(child) => *child,
+                    None => {
+                        automaton.children.push(HashMap::new());
+                        automaton.suffix_link.push(0);
+                        automaton.pattern_at.push(None);
+                        automaton.pattern_link.push(None);
+                        let child = automaton.children.len() - 1;
+                        automaton.children[node].insert(*c, child);
+                        child
+                    }
+                };
+            }
+            automaton.pattern_at[node].get_or_insert(p);
+        }
+        // Suffix links point to shallower nodes, so they are set breadth first.
+        let mut queue: VecDeque<usize> = VecDeque::from([0]);
+        while let Some(node) = queue.pop_front() {
+            let children: Vec<(char, usize)> = automaton.children[node]
+                .iter()
+                .map(|(c, n)| (*c, *n))
+                .collect();
+            for (c, child) in children {
+                let suffix = match node {
+                    0 => 0,
+                    _ => automaton.step(automaton.suffix_link[node], c),
+                };
+                automaton.suffix_link[child] = suffix;
+                automaton.pattern_link[child] = match automaton.pattern_at[suffix] {
+                    Some(_) => Some(suffix),
+                    None => automaton.pattern_link[suffix],
+                };
+                queue.push_back(child);
+            }
+        }
+        return automaton;
+    }
+
+    fn step(&self, mut node: usize, c: char) -> usize {
+        loop {
+            if let Some(child) = self.children[node].get(&c) {
+                return *child;
+            }
+            if node == 0 {
+                return 0;
+            }
+            node = self.suffix_link[node];
+        }
+    }
+
+    // The patterns ending at the scan position when at |node|, longest first.
+    fn patterns_ending_at(&self, node: usize) -> impl Iterator<Item = usize> + '_ {
+        let first = match self.pattern_at[node] {
+            Some(_) => Some(node),
+            None => self.pattern_link[node],
+        };
+        return std::iter::successors(first, |n| self.pattern_link[*n])
+            .map(|n| self.pattern_at[n].unwrap());
+    }
+}
+
+// Finds the tokens of a vocabulary in calibration lines. Overlapping tokens are all found, so
+// "oneight" has both a one and an eight. A second automaton of the reversed tokens scans lines
+// from the end, to find the last token without scanning the whole line.
+struct Scanner {
+    tokens: Vec<(Vec<char>, u32)>,
+    forward: Automaton,
+    backward: Automaton,
+    max_token_len: usize,
+}
+
+impl Scanner {
+    fn new(vocabulary: &Vocabulary) -> Scanner {
+        let tokens: Vec<(Vec<char>, u32)> = vocabulary
+            .tokens
+            .iter()
+            .map(|(token, value)| (token.chars().collect(), *value))
+            .collect();
+        let patterns: Vec<Vec<char>> = tokens.iter().map(|(t, _)| t.clone()).collect();
+        let reversed: Vec<Vec<char>> = patterns
+            .iter()
+            .map(|p| p.iter().rev().copied().collect())
+            .collect();
+        return Scanner {
+            forward: Automaton::new(&patterns),
+            backward: Automaton::new(&reversed),
+            max_token_len: patterns.iter().map(|p| p.len()).max().unwrap_or(0),
+            tokens,
+        };
+    }
+
+    // All matches in the line, by start and then longest first.
+    fn all_matches(&self, line: &str) -> Vec<Match> {
+        let mut matches: Vec<Match> = Vec::new();
+        let mut node = 0;
+        for (i, c) in line.chars().enumerate() {
+            node = self.forward.step(node, c);
+            for p in self.forward.patterns_ending_at(node) {
+                let (token, value) = &self.tokens[p];
+                matches.push(Match {
+                    start: i + 1 - token.len(),
+                    end: i + 1,
+                    value: *value,
+                });
+            }
+        }
+        matches.sort_by_key(|m| (m.start, std::cmp::Reverse(m.end)));
+        return matches;
+    }
+
+    // The match that starts first, the longest one if several do. Matches are found by their
+    // end, so the scan goes on until no later match can start before the best one.
+    fn first_match(&self, line: &str) -> Option<Match> {
+        let mut best: Option<Match> = None;
+        let mut node = 0;
+        for (i, c) in line.chars().enumerate() {
+            if matches!(&best, Some(m) if i >= m.start + self.max_token_len) {
+                break;
+            }
+            node = self.forward.step(node, c);
+            for p in self.forward.patterns_ending_at(node) {
+                let len = self.tokens[p].0.len();
+                let start = i + 1 - len;
+                let is_better = match &best {
+                    None => true,
+                    Some(m) => start < m.start || (start == m.start && i + 1 > m.end),
+                };
+                if is_better {
+                    best = Some(Match {
+                        start,
+                        end: i + 1,
+                        value: self.tokens[p].1,
+                    });
+                }
+            }
+        }
+        return best;
+    }
+
+    // The match that starts last, the longest one if several do. Scanning the reversed line,
+    // matches are found by their start, from the last, longest first.
+    fn last_match(&self, line: &str) -> Option<Match> {
+        let chars: Vec<char> = line.chars().collect();
+        let mut node = 0;
+        for (i, c) in chars.iter().enumerate().rev() {
+            node = self.backward.step(node, *c);
+            if let Some(p) = self.backward.patterns_ending_at(node).next() {
+                let (token, value) = &self.tokens[p];
+                return Some(Match {
+                    start: i,
+                    end: i + token.len(),
+                    value: *value,
+                });
+            }
+        }
+        return None;
+    }
+
+    // The first and last digits of the line, as a two-digit number.
+    fn calibration_value(&self, line: &str) -> Option<u32> {
+        return Some(10 * self.first_match(line)?.value + self.last_match(line)?.value);
+    }
+
+    fn calibration_sum(&self, text: &str) -> u32 {
+        let mut calibration_sum = 0;
+        for (i, line) in text.split("\n").enumerate() {
+            match self.calibration_value(line) {
+                Some(value) => calibration_sum += value,
+                None => panic!("line {} has no digit: {line}", i + 1),
+            }
+        }
+        return calibration_sum;
+    }
+}
 
 pub fn solve_part_1(text: &String) -> () {
-    let calibration_sum: i32 = solve_either(text, /*include_words=*/ false);
+    let calibration_sum = Scanner::new(&Vocabulary::digits()).calibration_sum(text);
     println!("Calibration value sum:  {}", calibration_sum);
     println!("Expected puzzle answer: 53194");
 }
 
 pub fn solve_part_2(text: &String) -> () {
-    let calibration_sum: i32 = solve_either(text, /*include_words=*/ true);
+    let vocabulary = match Vocabulary::digits_and_words(&LANGUAGES[0].1) {
+        Ok(vocabulary) => vocabulary,
+        Err(e) => panic!("bad vocabulary: {e}"),
+    };
+    let calibration_sum = Scanner::new(&vocabulary).calibration_sum(text);
     println!("Calibration value sum:  {}", calibration_sum);
     println!("Expected puzzle answer: 54249");
 }
 
-fn solve_either(text: &String, include_words: bool) -> i32 {
-    let lines: Vec<String> = text.split("\n").map(|s| s.to_string()).collect();
-    let mut str_to_digit: HashMap<&str, i32> = HashMap::from([
-        ("0", 0),
-        ("1", 1),
-        ("2", 2),
-        ("3", 3),
-        ("4", 4),
-        ("5", 5),
-        ("6", 6),
-        ("7", 7),
-        ("8", 8),
-        ("9", 9),
-    ]);
-    if include_words {
-        for (word, value) in [
-            ("zero", 0),
-            ("one", 1),
-            ("two", 2),
-            ("three", 3),
-            ("four", 4),
-            ("five", 5),
-            ("six", 6),
-            ("seven", 7),
-            ("eight", 8),
-            ("nine", 9),
-        ] {
-            str_to_digit.insert(word, value);
+// Prints every token found in each line with its position, under the part 2 vocabulary, and
+// the calibration value sum with the words of each language.
+pub fn print_matches(text: &String) -> () {
+    let mut scanners: Vec<(&str, Scanner)> = Vec::new();
+    for (language, words) in &LANGUAGES {
+        match Vocabulary::digits_and_words(words) {
+            Ok(vocabulary) => scanners.push((language, Scanner::new(&vocabulary))),
+            Err(e) => println!("Bad {language} vocabulary: {e}"),
         }
     }
-    let mut calibration_values: Vec<i32> = Vec::new();
-    for line in lines {
-        let mut first: Option<i32> = None;
-        let mut last: Option<i32> = None;
-        for (i, _) in line.chars().enumerate() {
-            for (s, v) in &str_to_digit {
-                if line[i..].starts_with(s) {
-                    match first {
-                        Some(_) => {}
-                        None => {
-                            first = Some(*v);
-                        }
-                    }
-                    last = Some(*v);
-                }
-            }
-        }
-        assert!(first.is_some());
-        assert!(last.is_some());
-        match (first, last) {
-            (Some(x), Some(y)) => {
-                calibration_values.push(10 * x + y);
-            }
-            _ => {}
+    let scanner = &scanners[0].1;
+    for line in text.split("\n") {
+        let chars: Vec<char> = line.chars().collect();
+        let matches: Vec<String> = scanner
+            .all_matches(line)
+            .iter()
+            .map(|m| {
+                let token: String = chars[m.start..m.end].iter().collect();
+                return format!("{token}={} at {}", m.value, m.start);
+            })
+            .collect();
+        match scanner.calibration_value(line) {
+            Some(value) => println!("{line}: {value} from {}", matches.join(", ")),
+            None => println!("{line}: no digits"),
         }
     }
-    let calibration_sum: i32 = calibration_values.iter().sum();
-    return calibration_sum;
+    for (language, scanner) in &scanners {
+        let values = text.split("\n").map(|line| scanner.calibration_value(line));
+        let calibration_sum: u32 = values.clone().flatten().sum();
+        let num_without_digits = values.filter(|v| v.is_none()).count();
+        println!(
+            "Calibration value sum with {language} words: {calibration_sum}, \
+             {num_without_digits} lines without digits"
+        );
+    }
 }
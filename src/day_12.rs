@@ -3,8 +3,10 @@ use nom::{
     multi::separated_list1,
 };
 
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
 use std::cmp;
-use std::collections::HashMap;
 
 // Reads a comma-separated list of integers into a vector.
 fn parse_list(input: &str) -> Vec<i64> {
@@ -18,88 +20,278 @@ fn parse_list(input: &str) -> Vec<i64> {
     }
 }
 
-fn count_placements(s: &str, v: Vec<i64>, cache: &mut HashMap<(String, Vec<i64>), i64>) -> i64 {
-    if v.len() == 0 {
-        if s.find('#').is_none() {
-            return 1;
-        } else {
-            return 0;
+// The placements of runs of damaged springs in a row, as a table over suffixes of the row:
+// num_suffix_placements[i][k] is the number of ways to place runs k.. in cells i.. .
+// Counts are exact; rows with too many arrangements for a u128 are rejected.
+// The table supports enumerating, unranking and sampling arrangements, and finding the cells
+// that are the same in every arrangement.
+pub struct RowPlacements {
+    cells: Vec<char>,
+    runs: Vec<usize>,
+    num_suffix_placements: Vec<Vec<u128>>,
+    // Number of '.' among the first i cells, to check if a run fits in constant time.
+    num_operational_before: Vec<usize>,
+}
+
+// A step from state (i, k), at cell i with runs k.. left to place.
+enum PlacementStep {
+    // Cell i is operational.
+    Operational,
+    // Run k is placed at cell i, followed by an operational cell unless at the end of the row.
+    Run,
+}
+
+impl RowPlacements {
+    pub fn new(cells: &str, runs: &Vec<usize>) -> Result<RowPlacements, String> {
+        let cells: Vec<char> = cells.chars().collect();
+        let n = cells.len();
+        let mut num_operational_before: Vec<usize> = vec![0; n + 1];
+        for (i, c) in cells.iter().enumerate() {
+            num_operational_before[i + 1] = num_operational_before[i] + (*c == '.') as usize;
+        }
+        let mut placements = RowPlacements {
+            cells,
+            runs: runs.clone(),
+            num_suffix_placements: vec![vec![0; runs.len() + 1]; n + 1],
+            num_operational_before,
+        };
+        placements.num_suffix_placements[n][runs.len()] = 1;
+        for i in (0..n).rev() {
+            for k in 0..runs.len() + 1 {
+                let count = placements.steps(i, k).iter().flatten().try_fold(
+                    0u128,
+                    |sum, (_, (ni, nk))| {
+                        sum.checked_add(placements.num_suffix_placements[*ni][*nk])
+                    },
+                );
+                placements.num_suffix_placements[i][k] = match count {
+                    Some(count) => count,
+                    None => return Err(format!("more than {} arrangements", u128::MAX)),
+                };
+            }
         }
+        return Ok(placements);
     }
-    if s.len() == 0 {
-        return 0;
+
+    // The possible steps from state (i, k), with the state they lead to, as an array rather
+    // than a vector since this is the innermost loop of every table.
+    fn steps(&self, i: usize, k: usize) -> [Option<(PlacementStep, (usize, usize))>; 2] {
+        let n = self.cells.len();
+        let mut steps = [None, None];
+        if i >= n {
+            return steps;
+        }
+        if self.cells[i] != '#' {
+            steps[0] = Some((PlacementStep::Operational, (i + 1, k)));
+        }
+        if k < self.runs.len() {
+            let end = i + self.runs[k];
+            if end <= n
+                && self.num_operational_before[end] == self.num_operational_before[i]
+                && (end == n || self.cells[end] != '#')
+            {
+                steps[1] = Some((PlacementStep::Run, (cmp::min(end + 1, n), k + 1)));
+            }
+        }
+        return steps;
     }
-    let mut sum = 0;
-    let min_len = v.iter().sum::<i64>() as usize + v.len() - 1;
-    // We cannot start after a '#', need to match all.
-    // Also don't even try starting if all of v doesn't fit in the string.
-    let max_start_index = cmp::min(
-        s.chars().position(|c| c == '#').unwrap_or_else(|| s.len()),
-        s.len() - min_len,
-    );
-    for i in 0..max_start_index + 1 {
-        // Try to place the next entry in v at index i.
-        let j = i + v[0] as usize;
-        if s[i..j].find('.').is_none() && (s.len() == j || s.chars().nth(j).unwrap() != '#') {
-            let v_remainder = v[1..].iter().cloned().collect::<Vec<i64>>();
-            if s.len() == j {
-                sum += count_placements("", v_remainder, cache);
-            } else {
-                let str_remainder = &s[j + 1..];
-                let num_arrangements: i64;
-                match cache.get(&(str_remainder.to_string(), v_remainder.clone())) {
-                    Some(n) => {
-                        num_arrangements = *n;
+
+    pub fn num_placements(&self) -> u128 {
+        return self.num_suffix_placements[0][0];
+    }
+
+    // Appends the cells a step from state (i, k) decides.
+    fn append_step(&self, arrangement: &mut String, step: &PlacementStep, i: usize, k: usize) {
+        match step {
+            PlacementStep::Operational => arrangement.push('.'),
+            PlacementStep::Run => {
+                let end = i + self.runs[k];
+                arrangement.extend(std::iter::repeat_n('#', self.runs[k]));
+                if end < self.cells.len() {
+                    arrangement.push('.');
+                }
+            }
+        }
+    }
+
+    // Enumerates the arrangements lazily, in lexicographic order with '#' < '.'.
+    fn arrangements(&self) -> impl Iterator<Item = String> + '_ {
+        // Partial arrangements, each with the state it has reached.
+        let mut stack: Vec<(usize, usize, String)> = Vec::new();
+        if self.num_placements() > 0 {
+            stack.push((0, 0, String::new()));
+        }
+        return std::iter::from_fn(move || {
+            while let Some((i, k, arrangement)) = stack.pop() {
+                if i == self.cells.len() {
+                    return Some(arrangement);
+                }
+                // Push operational steps first, so that runs are popped first. Only steps
+                // which can be completed are taken, so no work is wasted on dead ends.
+                for (step, (ni, nk)) in self.steps(i, k).into_iter().flatten() {
+                    if self.num_suffix_placements[ni][nk] == 0 {
+                        continue;
                     }
-                    None => {
-                        num_arrangements =
-                            count_placements(str_remainder, v_remainder.clone(), cache);
-                        cache.insert(
-                            (str_remainder.to_string(), v_remainder.clone()),
-                            num_arrangements,
-                        );
+                    let mut next = arrangement.clone();
+                    self.append_step(&mut next, &step, i, k);
+                    stack.push((ni, nk, next));
+                }
+            }
+            return None;
+        });
+    }
+
+    // The arrangement at |index| in the order of |arrangements|, if there are that many.
+    fn nth_arrangement(&self, mut index: u128) -> Option<String> {
+        if index >= self.num_placements() {
+            return None;
+        }
+        let mut arrangement = String::new();
+        let (mut i, mut k) = (0, 0);
+        while i < self.cells.len() {
+            // Runs come first in the order.
+            for (step, (ni, nk)) in self.steps(i, k).into_iter().rev().flatten() {
+                let count = self.num_suffix_placements[ni][nk];
+                if index < count {
+                    self.append_step(&mut arrangement, &step, i, k);
+                    (i, k) = (ni, nk);
+                    break;
+                }
+                index -= count;
+            }
+        }
+        return Some(arrangement);
+    }
+
+    // An arrangement drawn uniformly at random, if there is any.
+    fn sample(&self, rng: &mut StdRng) -> Option<String> {
+        if self.num_placements() == 0 {
+            return None;
+        }
+        return self.nth_arrangement(rng.gen_range(0..self.num_placements()));
+    }
+
+    // The row with each cell that is the same in every arrangement set to that, and the others
+    // to '?', or None if there is no arrangement.
+    // The number of arrangements with a run placed at (i, k) is the number of ways to reach
+    // (i, k) from the start, times the number of ways to finish from after the run.
+    // Only states from which the row can be finished are reached, so that every count here is
+    // at most the total and cannot overflow.
+    pub fn forced_cells(&self) -> Option<String> {
+        let n = self.cells.len();
+        let total = self.num_placements();
+        if total == 0 {
+            return None;
+        }
+        let mut num_prefix_placements: Vec<Vec<u128>> = vec![vec![0; self.runs.len() + 1]; n + 1];
+        num_prefix_placements[0][0] = 1;
+        // Number of arrangements in which each cell is damaged, as differences between cells.
+        let mut damaged_diffs: Vec<u128> = vec![0; n + 1];
+        let mut damaged_ends: Vec<u128> = vec![0; n + 1];
+        for i in 0..n {
+            for k in 0..self.runs.len() + 1 {
+                let num_prefixes = num_prefix_placements[i][k];
+                if num_prefixes == 0 {
+                    continue;
+                }
+                for (step, (ni, nk)) in self.steps(i, k).into_iter().flatten() {
+                    if self.num_suffix_placements[ni][nk] == 0 {
+                        continue;
+                    }
+                    num_prefix_placements[ni][nk] += num_prefixes;
+                    if let PlacementStep::Run = step {
+                        let count = num_prefixes * self.num_suffix_placements[ni][nk];
+                        damaged_diffs[i] += count;
+                        damaged_ends[i + self.runs[k]] += count;
                     }
                 }
-                sum += num_arrangements;
             }
         }
+        let mut forced = String::new();
+        let mut num_damaged: u128 = 0;
+        for i in 0..n {
+            num_damaged = num_damaged + damaged_diffs[i] - damaged_ends[i];
+            forced.push(match num_damaged {
+                0 => '.',
+                d if d == total => '#',
+                _ => '?',
+            });
+        }
+        return Some(forced);
+    }
+}
+
+// Parses a row "???.### 1,1,3" into the cells and the runs.
+fn parse_row(line: &str) -> (&str, Vec<usize>) {
+    let space_index = line.find(' ').unwrap();
+    let runs = parse_list(&line[space_index + 1..])
+        .iter()
+        .map(|r| *r as usize)
+        .collect();
+    return (&line[..space_index], runs);
+}
+
+const MAX_PRINTED_ARRANGEMENTS: usize = 10;
+const SAMPLING_SEED: u64 = 12;
+
+// Prints, for each row, the number of arrangements, the forced cells, the first arrangements
+// and a uniformly sampled one.
+pub fn print_arrangements(text: &String) -> () {
+    let mut rng = StdRng::seed_from_u64(SAMPLING_SEED);
+    for line in text.split("\n") {
+        let (cells, runs) = parse_row(line);
+        let placements = match RowPlacements::new(cells, &runs) {
+            Ok(placements) => placements,
+            Err(e) => {
+                println!("{line}: {e}");
+                continue;
+            }
+        };
+        println!("{line}: {} arrangements", placements.num_placements());
+        match placements.forced_cells() {
+            Some(forced) => println!("  forced:  {forced}"),
+            None => continue,
+        }
+        for arrangement in placements.arrangements().take(MAX_PRINTED_ARRANGEMENTS) {
+            println!("  {arrangement}");
+        }
+        if placements.num_placements() > MAX_PRINTED_ARRANGEMENTS as u128 {
+            println!("  ...");
+        }
+        println!("  sampled: {}", placements.sample(&mut rng).unwrap());
     }
-    return sum;
 }
 
-fn count_arrangements(text: &String, row_multiplier: usize) -> i64 {
-    let lines: Vec<String> = text.split("\n").map(|s| s.to_string()).collect();
-    let mut cache: HashMap<(String, Vec<i64>), i64> = HashMap::new();
-    let mut sum = 0;
-    for line in lines {
-        let space_index = line.find(' ').unwrap();
-        let input_string = &line[..space_index];
-        let s = std::iter::repeat(input_string.to_string())
-            .take(row_multiplier)
-            .collect::<Vec<String>>()
-            .join("?");
-        let input_vector = parse_list(&line[space_index + 1..]);
-        let v = input_vector
-            .iter()
-            .cycle()
-            .take(input_vector.len() * row_multiplier)
-            .cloned()
-            .collect::<Vec<i64>>();
-        let num_arrangements = count_placements(&s, v.clone(), &mut cache);
-        sum += num_arrangements;
+fn count_arrangements(text: &String, row_multiplier: usize) -> Result<u128, String> {
+    let mut sum: u128 = 0;
+    for line in text.split("\n") {
+        let (cells, runs) = parse_row(line);
+        let unfolded_cells = vec![cells; row_multiplier].join("?");
+        let unfolded_runs = runs.repeat(row_multiplier);
+        let placements = RowPlacements::new(&unfolded_cells, &unfolded_runs)
+            .map_err(|e| format!("{line}: {e}"))?;
+        sum = sum
+            .checked_add(placements.num_placements())
+            .ok_or(format!("more than {} arrangements in total", u128::MAX))?;
     }
-    return sum;
+    return Ok(sum);
 }
 
 pub fn solve_part_1(text: &String) -> () {
-    let answer = count_arrangements(text, /*row_multiplier*/ 1);
+    let answer = match count_arrangements(text, /*row_multiplier*/ 1) {
+        Ok(answer) => answer,
+        Err(e) => panic!("unable to count: {e}"),
+    };
 
     println!("Number of solutions:    {answer}");
     println!("Expected puzzle answer: 7236");
 }
 
 pub fn solve_part_2(text: &String) -> () {
-    let answer = count_arrangements(text, /*row_multiplier*/ 5);
+    let answer = match count_arrangements(text, /*row_multiplier*/ 5) {
+        Ok(answer) => answer,
+        Err(e) => panic!("unable to count: {e}"),
+    };
 
     println!("Number of solutions:    {answer}");
     println!("Expected puzzle answer: 11607695322318");
@@ -1,109 +1,290 @@
 use crate::geometry::Point;
 
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
 
 const DIR_LEFT: Point = Point { x: -1, y: 0 };
 const DIR_RIGHT: Point = Point { x: 1, y: 0 };
 const DIR_UP: Point = Point { x: 0, y: 1 };
 const DIR_DOWN: Point = Point { x: 0, y: -1 };
 
-// Turns a string of newline-separated rows into a 2D matrix.
-// Reverses the y-axis, to convert from top-down text to "up is positive" y-axis.
-fn parse_map(input: &str) -> Vec<Vec<char>> {
-    let mut rows: Vec<Vec<char>> = Vec::new();
-    for line in input.split("\n") {
-        rows.push(line.chars().collect::<Vec<char>>());
+// A grid of bits, packed into 64-bit words row by row.
+#[derive(Clone, Eq, Hash, PartialEq)]
+struct BitGrid {
+    num_rows: usize,
+    num_cols: usize,
+    words_per_row: usize,
+    words: Vec<u64>,
+}
+
+// The bits from |lo| up to (excluding) |hi| of a word.
+fn word_mask(lo: usize, hi: usize) -> u64 {
+    if hi - lo == 64 {
+        return !0;
     }
-    rows.reverse();
-    return rows;
+    return ((1 << (hi - lo)) - 1) << lo;
 }
 
-fn tilt(map: &mut Vec<Vec<char>>, dir: Point) -> () {
-    let height = map.len() as i64;
-    let width = map[0].len() as i64;
-    // Start at closest row in tilt direction.
-    let mut start = Point { x: 0, y: 0 };
-    let bounds = Point {
-        x: width,
-        y: height,
-    };
-    let orthogonal_dir = Point { x: dir.y, y: dir.x };
-    if dir.x + dir.y > 0 {
-        // Reverse direction.
-        start = Point {
-            x: bounds.x - 1,
-            y: bounds.y - 1,
+impl BitGrid {
+    fn new(num_rows: usize, num_cols: usize) -> BitGrid {
+        let words_per_row = num_cols.div_ceil(64);
+        return BitGrid {
+            num_rows,
+            num_cols,
+            words_per_row,
+            words: vec![0; num_rows * words_per_row],
         };
     }
-    let is_within_bounds = |p: Point| p.x >= 0 && p.x < width && p.y >= 0 && p.y < height;
-    // Loop over all point at fixed dir-distance from edge, moving each step-by-step until we hit another rock or the edge.
-    while is_within_bounds(start) {
-        let mut p = start;
-        while is_within_bounds(p) {
-            if map[p.y as usize][p.x as usize] != 'O' {
-                p = p - dir;
+
+    fn get(&self, row: usize, col: usize) -> bool {
+        return self.words[row * self.words_per_row + col / 64] & (1 << (col % 64)) != 0;
+    }
+
+    fn set(&mut self, row: usize, col: usize) -> () {
+        self.words[row * self.words_per_row + col / 64] |= 1 << (col % 64);
+    }
+
+    fn clear(&mut self, row: usize, col: usize) -> () {
+        self.words[row * self.words_per_row + col / 64] &= !(1 << (col % 64));
+    }
+
+    // Calls |f| with the index and mask of each word overlapping columns |start|..|end| of a row.
+    fn for_each_word_in_range(
+        &self,
+        row: usize,
+        start: usize,
+        end: usize,
+        mut f: impl FnMut(usize, u64) -> (),
+    ) -> () {
+        if start >= end {
+            return;
+        }
+        for w in start / 64..(end - 1) / 64 + 1 {
+            let lo = start.max(w * 64) - w * 64;
+            let hi = end.min((w + 1) * 64) - w * 64;
+            f(row * self.words_per_row + w, word_mask(lo, hi));
+        }
+    }
+
+    fn count_ones_in_range(&self, row: usize, start: usize, end: usize) -> usize {
+        let mut count = 0;
+        self.for_each_word_in_range(row, start, end, |i, mask| {
+            count += (self.words[i] & mask).count_ones() as usize;
+        });
+        return count;
+    }
+
+    fn fill_range(&mut self, row: usize, start: usize, end: usize, value: bool) -> () {
+        let mut words = std::mem::take(&mut self.words);
+        self.for_each_word_in_range(row, start, end, |i, mask| match value {
+            true => words[i] |= mask,
+            false => words[i] &= !mask,
+        });
+        self.words = words;
+    }
+
+    // The (row, column) of each set bit, row by row.
+    fn ones(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        return self.words.iter().enumerate().flat_map(move |(i, word)| {
+            let (row, w) = (i / self.words_per_row, i % self.words_per_row);
+            let mut bits = *word;
+            std::iter::from_fn(move || {
+                if bits == 0 {
+                    return None;
+                }
+                let b = bits.trailing_zeros() as usize;
+                bits &= bits - 1;
+                return Some((row, w * 64 + b));
+            })
+        });
+    }
+
+    // Runs in time linear in the number of words and set bits.
+    fn transposed(&self) -> BitGrid {
+        let mut transposed = BitGrid::new(self.num_cols, self.num_rows);
+        for (row, col) in self.ones() {
+            transposed.set(col, row);
+        }
+        return transposed;
+    }
+}
+
+// The maximal ranges of columns without a set bit, per row.
+fn free_ranges(blocked: &BitGrid) -> Vec<Vec<(usize, usize)>> {
+    let mut ranges: Vec<Vec<(usize, usize)>> = vec![Vec::new(); blocked.num_rows];
+    for (row, row_ranges) in ranges.iter_mut().enumerate() {
+        let mut start = 0;
+        for col in 0..blocked.num_cols + 1 {
+            if col == blocked.num_cols || blocked.get(row, col) {
+                if start < col {
+                    row_ranges.push((start, col));
+                }
+                start = col + 1;
+            }
+        }
+    }
+    return ranges;
+}
+
+// Moves the set bits in each free range of each row to the start (or end) of the range, and
+// makes the same moves in |transposed|, which holds the same bits column by column. Ranges whose
+// bits are already in place are skipped, so only ranges with moving bits touch |transposed|.
+fn slide(
+    bits: &mut BitGrid,
+    transposed: &mut BitGrid,
+    ranges: &Vec<Vec<(usize, usize)>>,
+    to_start: bool,
+) -> () {
+    for (row, row_ranges) in ranges.iter().enumerate() {
+        for (start, end) in row_ranges {
+            let count = bits.count_ones_in_range(row, *start, *end);
+            let (packed_start, packed_end) = match to_start {
+                true => (*start, start + count),
+                false => (end - count, *end),
+            };
+            if bits.count_ones_in_range(row, packed_start, packed_end) == count {
                 continue;
             }
-            let mut q = p.clone();
-            while is_within_bounds(q) {
-                let nq = q + dir;
-                if !is_within_bounds(nq) || map[nq.y as usize][nq.x as usize] != '.' {
-                    break;
+            bits.for_each_word_in_range(row, *start, *end, |i, mask| {
+                let mut word = bits.words[i] & mask;
+                while word != 0 {
+                    let col = (i % bits.words_per_row) * 64 + word.trailing_zeros() as usize;
+                    transposed.clear(col, row);
+                    word &= word - 1;
                 }
-                // There is an 'O' at q and a '.' in direction |dir|.
-                map[nq.y as usize][nq.x as usize] = 'O';
-                map[q.y as usize][q.x as usize] = '.';
-                q = nq;
+            });
+            bits.fill_range(row, *start, *end, false);
+            bits.fill_range(row, packed_start, packed_end, true);
+            for col in packed_start..packed_end {
+                transposed.set(col, row);
             }
-            p = p - dir;
         }
+    }
+}
 
-        start = start - orthogonal_dir;
+// The platform as bitboards, with row 0 the north edge. Tilting slides the rounded rocks over
+// the free ranges between cube-shaped rocks, one range at a time, counting the rocks in a range
+// with popcounts. Tilts north or south slide the columns of a column-major copy of the rocks,
+// which is kept in sync with the row-major one.
+struct Platform {
+    rounded_rocks: BitGrid,
+    rounded_rocks_by_col: BitGrid,
+    free_ranges_by_row: Vec<Vec<(usize, usize)>>,
+    free_ranges_by_col: Vec<Vec<(usize, usize)>>,
+}
+
+impl Platform {
+    fn tilt(&mut self, dir: Point) -> () {
+        match dir {
+            DIR_LEFT | DIR_RIGHT => {
+                slide(
+                    &mut self.rounded_rocks,
+                    &mut self.rounded_rocks_by_col,
+                    &self.free_ranges_by_row,
+                    dir == DIR_LEFT,
+                );
+            }
+            DIR_UP | DIR_DOWN => {
+                slide(
+                    &mut self.rounded_rocks_by_col,
+                    &mut self.rounded_rocks,
+                    &self.free_ranges_by_col,
+                    dir == DIR_UP,
+                );
+            }
+            _ => panic!("bad tilt direction"),
+        }
+    }
+
+    // Tilts north, west, south and east.
+    fn spin_cycle(&mut self) -> () {
+        for dir in [DIR_UP, DIR_LEFT, DIR_DOWN, DIR_RIGHT] {
+            self.tilt(dir);
+        }
+    }
+
+    // The total load on the north support beams.
+    fn north_load(&self) -> usize {
+        return self
+            .rounded_rocks
+            .ones()
+            .map(|(row, _)| self.rounded_rocks.num_rows - row)
+            .sum();
+    }
+
+    // A 64-bit hash of the rounded rock positions, to find repeated states without storing them.
+    fn rocks_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.rounded_rocks.hash(&mut hasher);
+        return hasher.finish();
     }
 }
 
-fn compute_weight(map: Vec<Vec<char>>) -> usize {
-    let mut total_load = 0;
-    for (i, row) in map.iter().enumerate() {
-        for (_, c) in row.iter().enumerate() {
-            if *c == 'O' {
-                total_load += i + 1;
+fn parse_platform(input: &str) -> Platform {
+    let lines: Vec<&str> = input.split("\n").collect();
+    let num_cols = lines[0].len();
+    let mut rounded_rocks = BitGrid::new(lines.len(), num_cols);
+    let mut cube_rocks = BitGrid::new(lines.len(), num_cols);
+    for (row, line) in lines.iter().enumerate() {
+        for (col, c) in line.chars().enumerate() {
+            match c {
+                'O' => rounded_rocks.set(row, col),
+                '#' => cube_rocks.set(row, col),
+                '.' => {}
+                _ => panic!("bad platform symbol"),
             }
         }
     }
-    return total_load;
+    return Platform {
+        rounded_rocks_by_col: rounded_rocks.transposed(),
+        rounded_rocks,
+        free_ranges_by_row: free_ranges(&cube_rocks),
+        free_ranges_by_col: free_ranges(&cube_rocks.transposed()),
+    };
 }
 
 pub fn solve_part_1(text: &String) -> () {
-    let mut map = parse_map(text);
-    tilt(&mut map, DIR_UP);
+    let mut platform = parse_platform(text);
+    platform.tilt(DIR_UP);
 
-    let total_load = compute_weight(map);
+    let total_load = platform.north_load();
 
     println!("Total rock load after tilt North: {total_load}");
     println!("Expected puzzle answer:           110565");
 }
 
 pub fn solve_part_2(text: &String) -> () {
-    let mut map = parse_map(text);
-    let mut cache: HashMap<Vec<Vec<char>>, i64> = HashMap::new();
+    let mut platform = parse_platform(text);
+    let mut cache: HashMap<u64, i64> = HashMap::new();
     let mut i = 0;
     let cycle_count = 1_000_000_000;
     while i < cycle_count {
-        tilt(&mut map, DIR_UP);
-        tilt(&mut map, DIR_LEFT);
-        tilt(&mut map, DIR_DOWN);
-        tilt(&mut map, DIR_RIGHT);
-        if let Some(j) = cache.get(&map) {
-            // We have detected a cycle, and can fast forward an integer number of those.
-            let short_circuit_cycle_count = (cycle_count - i) / (i - j);
-            i += short_circuit_cycle_count * (i - j);
-        }
-        cache.insert(map.clone(), i);
+        platform.spin_cycle();
         i += 1;
+        let hash = platform.rocks_hash();
+        if let Some(j) = cache.get(&hash) {
+            // We have likely detected a cycle. Confirm it by running it once, which rules out
+            // hash collisions without having stored the earlier states.
+            let period = i - j;
+            let state = platform.rounded_rocks.clone();
+            for _ in 0..period.min(cycle_count - i) {
+                platform.spin_cycle();
+            }
+            i += period.min(cycle_count - i);
+            if platform.rounded_rocks == state {
+                // Fast forward an integer number of cycles.
+                i += (cycle_count - i) / period * period;
+                for _ in i..cycle_count {
+                    platform.spin_cycle();
+                }
+                break;
+            }
+        }
+        cache.insert(platform.rocks_hash(), i);
     }
 
-    let total_load = compute_weight(map);
+    let total_load = platform.north_load();
 
     println!("Total rock load after spin cycling: {total_load}");
     println!("Expected puzzle answer:             89845");
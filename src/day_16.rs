@@ -2,6 +2,7 @@ use crate::geometry::Point;
 
 use std::cmp;
 
+use std::collections::HashMap;
 use std::collections::HashSet;
 use std::collections::VecDeque;
 
@@ -62,14 +63,18 @@ fn compute_beam_continuation((position, dir): (Point, Point), c: char) -> Vec<(P
     }
 }
 
-fn compute_num_energized(map: &Vec<Vec<char>>, initial_beam_head: (Point, Point)) -> usize {
+// A beam head is a position and direction of a beam.
+type BeamHead = (Point, Point);
+
+// Computes all beam heads reached from the initial beam.
+fn compute_beam_heads(map: &Vec<Vec<char>>, initial_beam_head: BeamHead) -> HashSet<BeamHead> {
     let height = map.len() as i64;
     let width = map[0].len() as i64;
     let is_within_bounds = |p: Point| p.x >= 0 && p.x < width && p.y >= 0 && p.y < height;
 
     // BFS from initial beam until all beam heads have left the map or entered a cycle.
-    let mut beam_heads: VecDeque<(Point, Point)> = VecDeque::from([initial_beam_head; 1]);
-    let mut visited: HashSet<(Point, Point)> = HashSet::new();
+    let mut beam_heads: VecDeque<BeamHead> = VecDeque::from([initial_beam_head; 1]);
+    let mut visited: HashSet<BeamHead> = HashSet::new();
     while let Some((p, d)) = beam_heads.pop_front() {
         visited.insert((p, d));
         let new_heads = compute_beam_continuation((p, d), map[p.y as usize][p.x as usize]);
@@ -79,11 +84,14 @@ fn compute_num_energized(map: &Vec<Vec<char>>, initial_beam_head: (Point, Point)
             }
         }
     }
+    return visited;
+}
 
-    let mut energized_tiles = visited
+fn compute_num_energized(map: &Vec<Vec<char>>, initial_beam_head: BeamHead) -> usize {
+    let mut energized_tiles = compute_beam_heads(map, initial_beam_head)
         .iter()
-        .map(|(position, _)| position)
-        .collect::<Vec<&Point>>();
+        .map(|(position, _)| *position)
+        .collect::<Vec<Point>>();
     energized_tiles.sort_unstable_by(|p, q| {
         if p.x != q.x {
             p.x.cmp(&q.x)
@@ -95,6 +103,40 @@ fn compute_num_energized(map: &Vec<Vec<char>>, initial_beam_head: (Point, Point)
     return energized_tiles.len();
 }
 
+// Draws the beams from the initial beam like the puzzle text: first the contraption with an
+// arrow on each empty tile a single beam passes, or the number of beams if more pass; then the
+// energized tiles as '#'.
+fn render_beams(map: &Vec<Vec<char>>, initial_beam_head: BeamHead) -> String {
+    let heads = compute_beam_heads(map, initial_beam_head);
+    let mut beams: Vec<Vec<char>> = map.clone();
+    let mut energized: Vec<Vec<char>> = map.iter().map(|row| vec!['.'; row.len()]).collect();
+    for (p, d) in &heads {
+        let (x, y) = (p.x as usize, p.y as usize);
+        energized[y][x] = '#';
+        beams[y][x] = match (map[y][x], beams[y][x]) {
+            ('.', '.') => match *d {
+                DIR_LEFT => '<',
+                DIR_RIGHT => '>',
+                DIR_UP => '^',
+                _ => 'v',
+            },
+            ('.', '<' | '>' | '^' | 'v') => '2',
+            ('.', n) => char::from_digit(n.to_digit(10).unwrap() + 1, 10).unwrap(),
+            (c, _) => c,
+        };
+    }
+    let mut lines: Vec<String> = Vec::new();
+    // Undo the y-axis reversal of parse_map.
+    for row in beams.iter().rev() {
+        lines.push(row.iter().collect());
+    }
+    lines.push(String::new());
+    for row in energized.iter().rev() {
+        lines.push(row.iter().collect());
+    }
+    return lines.join("\n");
+}
+
 pub fn solve_part_1(text: &String) -> () {
     let map = parse_map(text);
 
@@ -110,42 +152,271 @@ pub fn solve_part_1(text: &String) -> () {
     println!("Expected puzzle answer:    7477");
 }
 
-pub fn solve_part_2(text: &String) -> () {
+// Prints the beams of part 1, entering in the upper left corner heading rightward.
+pub fn print_beams(text: &String) -> () {
     let map = parse_map(text);
+    let top_left_map_corner = Point {
+        x: 0,
+        y: map.len() as i64 - 1,
+    };
+    println!("{}", render_beams(&map, (top_left_map_corner, DIR_RIGHT)));
+}
+
+// The beams entering the map from each edge tile, heading inwards.
+fn edge_entry_heads(map: &Vec<Vec<char>>) -> Vec<BeamHead> {
     let height = map.len() as i64;
     let width = map[0].len() as i64;
-
-    // Loop over all incoming beam positions and directions.
-    let mut max_num_energized_tiles = 0;
+    let mut heads: Vec<BeamHead> = Vec::new();
     for row in 0..height {
-        let head = (Point { x: 0, y: row }, DIR_RIGHT);
-        max_num_energized_tiles =
-            cmp::max(max_num_energized_tiles, compute_num_energized(&map, head));
-        let head = (
+        heads.push((Point { x: 0, y: row }, DIR_RIGHT));
+        heads.push((
             Point {
                 x: width - 1,
                 y: row,
             },
             DIR_LEFT,
-        );
-        max_num_energized_tiles =
-            cmp::max(max_num_energized_tiles, compute_num_energized(&map, head));
+        ));
     }
     for col in 0..width {
-        let head = (Point { x: col, y: 0 }, DIR_UP);
-        max_num_energized_tiles =
-            cmp::max(max_num_energized_tiles, compute_num_energized(&map, head));
-        let head = (
+        heads.push((Point { x: col, y: 0 }, DIR_UP));
+        heads.push((
             Point {
                 x: col,
                 y: height - 1,
             },
             DIR_DOWN,
-        );
-        max_num_energized_tiles =
-            cmp::max(max_num_energized_tiles, compute_num_energized(&map, head));
+        ));
+    }
+    return heads;
+}
+
+// The beam segments between splitters, as a graph with an edge from each segment to the two
+// segments its beam is split into. Segments in the same strongly connected component energize
+// the same tiles, so the tiles are computed once per component, as bitsets unioned over the
+// condensed graph.
+struct SegmentGraph {
+    segment_of_head: HashMap<BeamHead, usize>,
+    component_of_segment: Vec<usize>,
+    // Energized tiles per component, as bitsets indexed by y * width + x.
+    energized: Vec<Vec<u64>>,
+}
+
+impl SegmentGraph {
+    fn new(map: &Vec<Vec<char>>, entry_heads: &Vec<BeamHead>) -> SegmentGraph {
+        let height = map.len() as i64;
+        let width = map[0].len() as i64;
+        let is_within_bounds = |p: Point| p.x >= 0 && p.x < width && p.y >= 0 && p.y < height;
+
+        // Trace the segments starting at the entry heads, and at the heads of split beams.
+        let mut segment_of_head: HashMap<BeamHead, usize> = HashMap::new();
+        let mut segment_tiles: Vec<Vec<Point>> = Vec::new();
+        let mut segment_next_heads: Vec<Vec<BeamHead>> = Vec::new();
+        let mut to_trace: Vec<BeamHead> = entry_heads.clone();
+        while let Some(first_head) = to_trace.pop() {
+            if segment_of_head.contains_key(&first_head) {
+                continue;
+            }
+            segment_of_head.insert(first_head, segment_tiles.len());
+            let mut tiles: Vec<Point> = Vec::new();
+            let mut next_heads: Vec<BeamHead> = Vec::new();
+            // Without splitting, the beam can loop back onto itself, e.g. passing through the
+            // splitter it came from.
+            let mut seen: HashSet<BeamHead> = HashSet::new();
+            let mut head = first_head;
+            while is_within_bounds(head.0) && seen.insert(head) {
+                tiles.push(head.0);
+                let continuation =
+                    compute_beam_continuation(head, map[head.0.y as usize][head.0.x as usize]);
+                if continuation.len() > 1 {
+                    next_heads = continuation
+                        .into_iter()
+                        .filter(|h| is_within_bounds(h.0))
+                        .collect();
+                    break;
+                }
+                head = continuation[0];
+            }
+            to_trace.extend(&next_heads);
+            segment_tiles.push(tiles);
+            segment_next_heads.push(next_heads);
+        }
+        let successors: Vec<Vec<usize>> = segment_next_heads
+            .iter()
+            .map(|heads| heads.iter().map(|h| segment_of_head[h]).collect())
+            .collect();
+
+        // Tarjan's algorithm, which finds the components in reverse topological order.
+        let components = strongly_connected_components(&successors);
+        let mut component_of_segment: Vec<usize> = vec![0; successors.len()];
+        for (c, segments) in components.iter().enumerate() {
+            for s in segments {
+                component_of_segment[*s] = c;
+            }
+        }
+        let num_words = ((width * height) as usize).div_ceil(64);
+        let mut energized: Vec<Vec<u64>> = Vec::new();
+        for (c, segments) in components.iter().enumerate() {
+            let mut bits: Vec<u64> = vec![0; num_words];
+            for s in segments {
+                for p in &segment_tiles[*s] {
+                    let i = (p.y * width + p.x) as usize;
+                    bits[i / 64] |= 1 << (i % 64);
+                }
+                // Successors in other components come earlier in the order, so are done.
+                for t in &successors[*s] {
+                    let t_component = component_of_segment[*t];
+                    if t_component == c {
+                        continue;
+                    }
+                    for (word, other) in bits.iter_mut().zip(&energized[t_component]) {
+                        *word |= other;
+                    }
+                }
+            }
+            energized.push(bits);
+        }
+        return SegmentGraph {
+            segment_of_head,
+            component_of_segment,
+            energized,
+        };
+    }
+
+    // The number of tiles energized by a beam entering at |head|, which must be one of the entry
+    // heads the graph was built for.
+    fn num_energized(&self, head: &BeamHead) -> usize {
+        let component = self.component_of_segment[self.segment_of_head[head]];
+        return self.energized[component]
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum();
+    }
+}
+
+// Finds the strongly connected components of a graph given by its adjacency lists, in reverse
+// topological order, by an iterative version of Tarjan's algorithm.
+fn strongly_connected_components(successors: &Vec<Vec<usize>>) -> Vec<Vec<usize>> {
+    let n = successors.len();
+    let mut index: Vec<Option<usize>> = vec![None; n];
+    let mut low_link: Vec<usize> = vec![0; n];
+    let mut on_stack: Vec<bool> = vec![false; n];
+    let mut stack: Vec<usize> = Vec::new();
+    let mut components: Vec<Vec<usize>> = Vec::new();
+    let mut next_index = 0;
+    for root in 0..n {
+        if index[root].is_some() {
+            continue;
+        }
+        // The DFS call stack, as vertices with the position of the next successor to visit.
+        let mut call_stack: Vec<(usize, usize)> = vec![(root, 0)];
+        index[root] = Some(next_index);
+        low_link[root] = next_index;
+        next_index += 1;
+        stack.push(root);
+        on_stack[root] = true;
+        while let Some((v, i)) = call_stack.pop() {
+            if i < successors[v].len() {
+                call_stack.push((v, i + 1));
+                let w = successors[v][i];
+                match index[w] {
+                    None => {
+                        index[w] = Some(next_index);
+                        low_link[w] = next_index;
+                        next_index += 1;
+                        stack.push(w);
+                        on_stack[w] = true;
+                        call_stack.push((w, 0));
+                    }
+                    Some(w_index) => {
+                        if on_stack[w] {
+                            low_link[v] = cmp::min(low_link[v], w_index);
+                        }
+                    }
+                }
+                continue;
+            }
+            // All successors of v are done.
+            if let Some((parent, _)) = call_stack.last() {
+                low_link[*parent] = cmp::min(low_link[*parent], low_link[v]);
+            }
+            if Some(low_link[v]) == index[v] {
+                let mut component: Vec<usize> = Vec::new();
+                loop {
+                    let w = stack.pop().unwrap();
+                    on_stack[w] = false;
+                    component.push(w);
+                    if w == v {
+                        break;
+                    }
+                }
+                components.push(component);
+            }
+        }
     }
+    return components;
+}
+
+pub fn solve_part_2(text: &String) -> () {
+    let map = parse_map(text);
+    let entry_heads = edge_entry_heads(&map);
+    let graph = SegmentGraph::new(&map, &entry_heads);
+
+    // Loop over all incoming beam positions and directions.
+    let max_num_energized_tiles = entry_heads
+        .iter()
+        .map(|head| graph.num_energized(head))
+        .max()
+        .unwrap();
 
     println!("Maximum number of energized tiles: {max_num_energized_tiles}");
     println!("Expected puzzle answer:            7853");
 }
+
+fn heading_name(dir: Point) -> &'static str {
+    return match dir {
+        DIR_LEFT => "left",
+        DIR_RIGHT => "right",
+        DIR_UP => "up",
+        _ => "down",
+    };
+}
+
+// Prints the number of energized tiles for each entry point, as the top-down row and column
+// of the entry tile and the direction the beam is heading.
+pub fn print_entry_report(text: &String) -> () {
+    let map = parse_map(text);
+    let height = map.len() as i64;
+    let entry_heads = edge_entry_heads(&map);
+    let graph = SegmentGraph::new(&map, &entry_heads);
+    for head in &entry_heads {
+        let (p, d) = head;
+        let heading = heading_name(*d);
+        println!(
+            "Row {:3} column {:3} heading {heading:5}: {} energized tiles",
+            height - 1 - p.y,
+            p.x,
+            graph.num_energized(head)
+        );
+    }
+}
+
+// Prints the beams of the entry point energizing the most tiles.
+pub fn print_best_beams(text: &String) -> () {
+    let map = parse_map(text);
+    let height = map.len() as i64;
+    let entry_heads = edge_entry_heads(&map);
+    let graph = SegmentGraph::new(&map, &entry_heads);
+    let best_head = *entry_heads
+        .iter()
+        .max_by_key(|head| graph.num_energized(head))
+        .unwrap();
+    let (p, d) = best_head;
+    println!(
+        "Best entry at row {} column {} heading {}, energizing {} tiles:",
+        height - 1 - p.y,
+        p.x,
+        heading_name(d),
+        graph.num_energized(&best_head)
+    );
+    println!("{}", render_beams(&map, best_head));
+}
@@ -2,6 +2,7 @@ use crate::geometry::Point;
 
 use std::cmp::Ordering;
 use std::collections::BinaryHeap;
+use std::collections::HashMap;
 use std::collections::HashSet;
 
 const DIR_LEFT: Point = Point { x: -1, y: 0 };
@@ -24,6 +25,28 @@ fn parse_map(input: &str) -> Vec<Vec<i64>> {
     return rows;
 }
 
+// How a crucible may move: it must move at least |min_straight| and at most |max_straight|
+// tiles in a straight line before turning (or stopping at the goal), and may only reverse
+// direction if |can_reverse| is set.
+struct CrucibleRules {
+    min_straight: i64,
+    max_straight: i64,
+    can_reverse: bool,
+}
+
+const CRUCIBLE: CrucibleRules = CrucibleRules {
+    min_straight: 1,
+    max_straight: 3,
+    can_reverse: false,
+};
+
+const ULTRA_CRUCIBLE: CrucibleRules = CrucibleRules {
+    min_straight: 4,
+    max_straight: 10,
+    can_reverse: false,
+};
+
+// The direction is zero before the first move.
 #[derive(Clone, Copy, Eq, Hash, PartialEq, PartialOrd, Ord)]
 struct MoveState {
     pos: Point,
@@ -31,15 +54,14 @@ struct MoveState {
     straight_counter: i64,
 }
 
-fn nbrs(state: &MoveState, map: &Vec<Vec<i64>>, use_ultra_crucible: bool) -> Vec<MoveState> {
+fn nbrs(state: &MoveState, map: &Vec<Vec<i64>>, rules: &CrucibleRules) -> Vec<MoveState> {
     let height = map.len() as i64;
     let width = map[0].len() as i64;
     let is_within_bounds = |p: Point| p.x >= 0 && p.x < width && p.y >= 0 && p.y < height;
     let mut ns: Vec<MoveState> = Vec::new();
     // We may move in 4 directions.
     for new_dir in [DIR_LEFT, DIR_RIGHT, DIR_UP, DIR_DOWN] {
-        // Don't revisit the position we came from, heat loss can only increase.
-        if new_dir == -state.dir {
+        if new_dir == -state.dir && !rules.can_reverse {
             continue;
         }
         // Crucible conditions on how many times we may move straight ahead.
@@ -47,20 +69,16 @@ fn nbrs(state: &MoveState, map: &Vec<Vec<i64>>, use_ultra_crucible: bool) -> Vec
             true => state.straight_counter + 1,
             false => 1,
         };
-        if use_ultra_crucible {
-            // Ultra crucibles must move at least 4 tiles before turning.
-            if state.straight_counter > 0 && state.straight_counter < 4 && new_dir != state.dir {
-                continue;
-            }
-            // Ultra crucibles cannot move more than 10 tiles in a straight line.
-            if new_straight_counter > 10 {
-                continue;
-            }
-        } else {
-            // Normal crucibles cannot move more than 3 tiles in a straight line.
-            if new_straight_counter > 3 {
-                continue;
-            }
+        // Crucibles must move some tiles before turning...
+        if state.straight_counter > 0
+            && state.straight_counter < rules.min_straight
+            && new_dir != state.dir
+        {
+            continue;
+        }
+        // ...and cannot move too many tiles in a straight line.
+        if new_straight_counter > rules.max_straight {
+            continue;
         }
         let new_pos = state.pos + new_dir;
         // We cannot leave the map.
@@ -81,6 +99,7 @@ fn nbrs(state: &MoveState, map: &Vec<Vec<i64>>, use_ultra_crucible: bool) -> Vec
 struct HeapState {
     heat_loss: i64,
     move_state: MoveState,
+    previous: Option<MoveState>,
 }
 
 impl Ord for HeapState {
@@ -92,6 +111,7 @@ impl Ord for HeapState {
             .heat_loss
             .cmp(&self.heat_loss)
             .then_with(|| self.move_state.cmp(&other.move_state))
+            .then_with(|| self.previous.cmp(&other.previous))
     }
 }
 
@@ -101,70 +121,124 @@ impl PartialOrd for HeapState {
     }
 }
 
-fn minimum_heat_loss_path(map: &Vec<Vec<i64>>, use_ultra_crucible: bool) -> i64 {
+// Finds the path from |start| to |goal| with the minimum heat loss, by Dijkstra's algorithm.
+// Returns the heat loss and the cells of the path, including start and goal, if there is a path.
+fn minimum_heat_loss_path(
+    map: &Vec<Vec<i64>>,
+    rules: &CrucibleRules,
+    start: Point,
+    goal: Point,
+) -> Option<(i64, Vec<Point>)> {
+    // The crucible need not move at all, whatever its minimum run.
+    if start == goal {
+        return Some((0, vec![start]));
+    }
     let mut queue: BinaryHeap<HeapState> = BinaryHeap::new();
     let mut visited: HashSet<MoveState> = HashSet::new();
-    let mut min_heat_loss: Option<i64> = None;
-    let start_pos = Point {
-        x: 0,
-        y: map.len() as i64 - 1,
-    };
-    let target_pos = Point {
-        x: map[0].len() as i64 - 1,
-        y: 0,
-    };
+    // The state each visited state was reached from, to reconstruct the path.
+    let mut previous: HashMap<MoveState, MoveState> = HashMap::new();
     queue.push(HeapState {
         heat_loss: 0,
         move_state: MoveState {
-            pos: start_pos,
-            dir: DIR_RIGHT,
+            pos: start,
+            dir: Point { x: 0, y: 0 },
             straight_counter: 0,
         },
+        previous: None,
     });
     while let Some(heap_state) = queue.pop() {
-        if visited.contains(&heap_state.move_state) {
+        let move_state = heap_state.move_state;
+        if visited.contains(&move_state) {
             continue;
         }
-        if heap_state.move_state.pos == target_pos {
-            min_heat_loss = Some(heap_state.heat_loss);
-            break;
+        if let Some(p) = heap_state.previous {
+            previous.insert(move_state, p);
         }
-        visited.insert(heap_state.move_state);
+        if move_state.pos == goal && move_state.straight_counter >= rules.min_straight {
+            let mut path: Vec<Point> = vec![move_state.pos];
+            let mut state = move_state;
+            while let Some(p) = previous.get(&state) {
+                path.push(p.pos);
+                state = *p;
+            }
+            path.reverse();
+            return Some((heap_state.heat_loss, path));
+        }
+        visited.insert(move_state);
         // Once the crucible may turn, it is never better to enter a state later (=>at higher heat loss) with higher straight-movement counter (=> fewer future options for directions).
         // Hence we count all such state as visited together with the current one.
-        let min_tiles_before_turning = match use_ultra_crucible {
-            true => 4,
-            false => 0,
-        };
-        if heap_state.move_state.straight_counter >= min_tiles_before_turning {
-            for c in heap_state.move_state.straight_counter..11 {
+        if move_state.straight_counter >= rules.min_straight {
+            for c in move_state.straight_counter..rules.max_straight + 1 {
                 let visited_state = MoveState {
-                    pos: heap_state.move_state.pos,
-                    dir: heap_state.move_state.dir,
+                    pos: move_state.pos,
+                    dir: move_state.dir,
                     straight_counter: c,
                 };
                 visited.insert(visited_state);
             }
         }
-        for neighbor in nbrs(&heap_state.move_state, &map, use_ultra_crucible) {
+        for neighbor in nbrs(&move_state, map, rules) {
             if visited.contains(&neighbor) {
                 continue;
             }
             let heat_loss =
                 heap_state.heat_loss + map[neighbor.pos.y as usize][neighbor.pos.x as usize];
             queue.push(HeapState {
-                heat_loss: heat_loss,
+                heat_loss,
                 move_state: neighbor,
+                previous: Some(move_state),
             });
         }
     }
 
-    return min_heat_loss.unwrap();
+    return None;
+}
+
+// Finds the minimum heat loss path from the top-left to the bottom-right corner.
+fn corner_to_corner_path(map: &Vec<Vec<i64>>, rules: &CrucibleRules) -> (i64, Vec<Point>) {
+    let start = Point {
+        x: 0,
+        y: map.len() as i64 - 1,
+    };
+    let goal = Point {
+        x: map[0].len() as i64 - 1,
+        y: 0,
+    };
+    return minimum_heat_loss_path(map, rules, start, goal).expect("no path to the goal");
+}
+
+// Draws the path over the map like the puzzle text, with arrows for the direction moved into
+// each cell.
+fn render_path(map: &Vec<Vec<i64>>, path: &Vec<Point>) -> String {
+    let mut rows: Vec<Vec<char>> = map
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|h| char::from_digit(*h as u32, 10).unwrap())
+                .collect()
+        })
+        .collect();
+    for pair in path.windows(2) {
+        let arrow = match pair[1] - pair[0] {
+            DIR_LEFT => '<',
+            DIR_RIGHT => '>',
+            DIR_UP => '^',
+            _ => 'v',
+        };
+        rows[pair[1].y as usize][pair[1].x as usize] = arrow;
+    }
+    // Undo the y-axis reversal of parse_map.
+    rows.reverse();
+    return rows
+        .iter()
+        .map(|row| row.iter().collect::<String>())
+        .collect::<Vec<String>>()
+        .join("\n");
 }
 
 pub fn solve_part_1(text: &String) -> () {
     let map = parse_map(text);
-    let min_heat_loss = minimum_heat_loss_path(&map, /*use_ultra_crucible*/ false);
+    let (min_heat_loss, _) = corner_to_corner_path(&map, &CRUCIBLE);
 
     println!("Minimum heat loss:      {min_heat_loss}");
     println!("Expected puzzle answer: 1044");
@@ -172,8 +246,18 @@ pub fn solve_part_1(text: &String) -> () {
 
 pub fn solve_part_2(text: &String) -> () {
     let map = parse_map(text);
-    let min_heat_loss = minimum_heat_loss_path(&map, /*use_ultra_crucible*/ true);
+    let (min_heat_loss, _) = corner_to_corner_path(&map, &ULTRA_CRUCIBLE);
 
     println!("Minimum heat loss:      {min_heat_loss}");
     println!("Expected puzzle answer: 1227");
 }
+
+// Prints the minimum heat loss paths of both kinds of crucibles over the map.
+pub fn print_paths(text: &String) -> () {
+    let map = parse_map(text);
+    for (name, rules) in [("Crucible", &CRUCIBLE), ("Ultra crucible", &ULTRA_CRUCIBLE)] {
+        let (min_heat_loss, path) = corner_to_corner_path(&map, rules);
+        println!("{name} path with heat loss {min_heat_loss}:");
+        println!("{}", render_path(&map, &path));
+    }
+}
@@ -1,96 +1,570 @@
 use nom::{
+    branch::alt,
     bytes::complete::tag,
-    character::complete::{alpha1, i64, line_ending, one_of},
-    combinator::all_consuming,
+    character::complete::{alpha1, i64, line_ending},
+    combinator::{all_consuming, map},
     error::Error,
     multi::{many0, separated_list1},
-    sequence::{delimited, preceded, terminated, tuple},
+    sequence::{delimited, preceded, separated_pair, terminated, tuple},
 };
 
 use std::cmp;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt;
 
-type Block = ((i64, i64), (i64, i64), (i64, i64), (i64, i64));
+#[derive(Clone, Copy, PartialEq)]
+enum Op {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+}
 
-fn volume((x, m, a, s): Block) -> i64 {
-    return (1 + x.1 - x.0) * (1 + m.1 - m.0) * (1 + a.1 - a.0) * (1 + s.1 - s.0);
+// A rule like "a<2006:qkq": if the category passes the comparison, send the part to the target.
+#[derive(Clone, Copy, PartialEq)]
+struct Rule<'a> {
+    category: &'a str,
+    op: Op,
+    value: i64,
+    target: &'a str,
 }
 
-pub fn solve_part_1(text: &String) -> () {
-    let (workflows, parts) = parse_input(text);
-    let initial_blocks = parts
-        .iter()
-        .map(|(x, m, a, s)| ((*x, *x), (*m, *m), (*a, *a), (*s, *s)))
-        .collect::<Vec<Block>>();
-    let accepted_rating_sum = get_accepted_blocks(workflows, initial_blocks)
-        .iter()
-        .fold(0, |sum, block| {
-            sum + block.0 .0 + block.1 .0 + block.2 .0 + block.3 .0
+impl fmt::Display for Rule<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = match self.op {
+            Op::Less => "<",
+            Op::LessOrEqual => "<=",
+            Op::Greater => ">",
+            Op::GreaterOrEqual => ">=",
+            Op::Equal => "==",
+        };
+        return write!(f, "{}{}{}:{}", self.category, op, self.value, self.target);
+    }
+}
+
+impl Rule<'_> {
+    // Returns the inclusive range of values passing the rule.
+    fn passing_range(&self) -> (i64, i64) {
+        return match self.op {
+            Op::Less => (i64::MIN, self.value - 1),
+            Op::LessOrEqual => (i64::MIN, self.value),
+            Op::Greater => (self.value + 1, i64::MAX),
+            Op::GreaterOrEqual => (self.value, i64::MAX),
+            Op::Equal => (self.value, self.value),
+        };
+    }
+
+    fn passes(&self, value: i64) -> bool {
+        let (lo, hi) = self.passing_range();
+        return lo <= value && value <= hi;
+    }
+}
+
+// Rules are tried in order, and the fallback target is used if none passes.
+#[derive(Clone, PartialEq)]
+struct Workflow<'a> {
+    rules: Vec<Rule<'a>>,
+    fallback: &'a str,
+}
+
+// The ratings of a part, by category, in input order.
+type Part<'a> = Vec<(&'a str, i64)>;
+
+// A hyper-rectangle of parts: an inclusive range of values per category.
+type Block<'a> = BTreeMap<&'a str, (i64, i64)>;
+
+fn volume(block: &Block) -> i64 {
+    return block.values().map(|(lo, hi)| 1 + hi - lo).product();
+}
+
+// Splits a block into the sub-block passing the rule, and the sub-blocks failing it.
+// Empty sub-blocks are discarded.
+fn split_block<'a>(
+    block: &Block<'a>,
+    rule: &Rule<'a>,
+) -> Result<(Option<Block<'a>>, Vec<Block<'a>>), String> {
+    let (lo, hi) = match block.get(rule.category) {
+        Some(range) => *range,
+        None => {
+            return Err(format!(
+                "rule {rule} tests unknown category {}",
+                rule.category
+            ));
+        }
+    };
+    let (pass_lo, pass_hi) = rule.passing_range();
+    let with_range = |range_lo: i64, range_hi: i64| -> Option<Block<'a>> {
+        if range_lo > range_hi {
+            return None;
+        }
+        let mut sub_block = block.clone();
+        sub_block.insert(rule.category, (range_lo, range_hi));
+        return Some(sub_block);
+    };
+    let pass = with_range(cmp::max(lo, pass_lo), cmp::min(hi, pass_hi));
+    let mut fail: Vec<Block> = Vec::new();
+    if pass_lo > i64::MIN {
+        fail.extend(with_range(lo, cmp::min(hi, pass_lo - 1)));
+    }
+    if pass_hi < i64::MAX {
+        fail.extend(with_range(cmp::max(lo, pass_hi + 1), hi));
+    }
+    return Ok((pass, fail));
+}
+
+// A step of a part through the workflows: the workflow, and the index of the rule that sent the
+// part on, or None if it was the fallback.
+type TraceStep<'a> = (&'a str, Option<usize>);
+
+// A set of workflows, starting at "in" and ending at "A" (accepted) or "R" (rejected).
+struct WorkflowEngine<'a> {
+    workflows: HashMap<&'a str, Workflow<'a>>,
+}
+
+impl<'a> WorkflowEngine<'a> {
+    fn get(&self, name: &str) -> Result<&Workflow<'a>, String> {
+        return self
+            .workflows
+            .get(name)
+            .ok_or_else(|| format!("unknown workflow {name}"));
+    }
+
+    // Sends a part through the workflows. Returns whether it is accepted, and the trace of
+    // the workflows and rules it passed through.
+    fn evaluate(&self, part: &Part) -> Result<(bool, Vec<TraceStep<'a>>), String> {
+        let mut trace: Vec<TraceStep> = Vec::new();
+        let mut label = "in";
+        while label != "A" && label != "R" {
+            if trace.iter().any(|(name, _)| *name == label) {
+                return Err(format!("workflow cycle through {label}"));
+            }
+            let workflow = self.get(label)?;
+            let mut next = (workflow.fallback, None);
+            for (i, rule) in workflow.rules.iter().enumerate() {
+                let value = match part.iter().find(|(c, _)| *c == rule.category) {
+                    Some((_, value)) => *value,
+                    None => {
+                        return Err(format!("part has no category {}", rule.category));
+                    }
+                };
+                if rule.passes(value) {
+                    next = (rule.target, Some(i));
+                    break;
+                }
+            }
+            trace.push((label, next.1));
+            label = next.0;
+        }
+        return Ok((label == "A", trace));
+    }
+
+    fn format_trace(&self, trace: &Vec<TraceStep>, is_accepted: bool) -> String {
+        let mut steps: Vec<String> = Vec::new();
+        for (name, rule_index) in trace {
+            let workflow = &self.workflows[name];
+            steps.push(match rule_index {
+                Some(i) => format!("{name} ({})", workflow.rules[*i]),
+                None => format!("{name} (fallback:{})", workflow.fallback),
+            });
+        }
+        steps.push(match is_accepted {
+            true => "A".to_string(),
+            false => "R".to_string(),
         });
+        return steps.join(" -> ");
+    }
+
+    // Returns the accepted sub-blocks of the initial blocks, starting at workflow "in".
+    fn accepted_blocks(&self, initial_blocks: Vec<Block<'a>>) -> Result<Vec<Block<'a>>, String> {
+        return self.propagate("in", initial_blocks, &mut |_, _, _| {});
+    }
+
+    // Sends the initial blocks through the workflows from |start|, and returns the accepted
+    // sub-blocks. Calls |on_branch| with the workflow, the index of the rule passed (None for
+    // the fallback) and the sub-block, for every non-empty sub-block taking a branch.
+    //
+    // Approach:
+    // Start with the initial blocks.
+    // Each rule will split the block along some axis, creating new (potentially empty) blocks.
+    // Keep applying rules and splitting blocks, sending them to different workflows.
+    // Discard any empty blocks encountered.
+    // When all blocks are accepted or rejected, stop and return the accepted blocks.
+    // All parts in a block have taken the same path, so a block reaching a workflow twice
+    // means that its parts would loop forever.
+    fn propagate(
+        &self,
+        start: &'a str,
+        initial_blocks: Vec<Block<'a>>,
+        on_branch: &mut impl FnMut(&'a str, Option<usize>, &Block<'a>),
+    ) -> Result<Vec<Block<'a>>, String> {
+        let mut stack: Vec<(&str, Block, Vec<&str>)> = initial_blocks
+            .into_iter()
+            .map(|b| (start, b, Vec::new()))
+            .collect();
+        let mut accepted_blocks: Vec<Block> = Vec::new();
+        while let Some((label, block, mut path)) = stack.pop() {
+            if label == "R" {
+                continue;
+            }
+            if label == "A" {
+                accepted_blocks.push(block);
+                continue;
+            }
+            if path.contains(&label) {
+                return Err(format!("workflow cycle: {} -> {label}", path.join(" -> ")));
+            }
+            path.push(label);
+            let workflow = self.get(label)?;
+            // Apply each rule, splitting the block of values into sub-blocks that either pass or fail each rule.
+            let mut remaining_blocks = vec![block];
+            for (i, rule) in workflow.rules.iter().enumerate() {
+                let mut failing_blocks = Vec::new();
+                for b in &remaining_blocks {
+                    let (b_pass, b_fail) = split_block(b, rule)?;
+                    if let Some(b_pass) = b_pass {
+                        on_branch(label, Some(i), &b_pass);
+                        stack.push((rule.target, b_pass, path.clone()));
+                    }
+                    failing_blocks.extend(b_fail);
+                }
+                remaining_blocks = failing_blocks;
+            }
+            for b in remaining_blocks {
+                on_branch(label, None, &b);
+                stack.push((workflow.fallback, b, path.clone()));
+            }
+        }
+        return Ok(accepted_blocks);
+    }
+
+    // Lints the workflows for parts within |initial_block|, see WorkflowAnalysis.
+    fn analyze(&self, initial_block: &Block<'a>) -> Result<WorkflowAnalysis<'a>, String> {
+        let mut taken_branches: HashSet<(&str, Option<usize>)> = HashSet::new();
+        self.propagate(
+            "in",
+            vec![initial_block.clone()],
+            &mut |name, rule_index, _| {
+                taken_branches.insert((name, rule_index));
+            },
+        )?;
+
+        let mut names: Vec<&str> = self.workflows.keys().copied().collect();
+        names.sort();
+        let mut analysis = WorkflowAnalysis {
+            dead_rules: Vec::new(),
+            dead_fallbacks: Vec::new(),
+            unreachable: Vec::new(),
+            always_accepting: Vec::new(),
+            always_rejecting: Vec::new(),
+        };
+        for name in names {
+            let workflow = &self.workflows[name];
+            if !taken_branches.iter().any(|(n, _)| *n == name) {
+                analysis.unreachable.push(name);
+                continue;
+            }
+            for i in 0..workflow.rules.len() {
+                if !taken_branches.contains(&(name, Some(i))) {
+                    analysis.dead_rules.push((name, i));
+                }
+            }
+            if !taken_branches.contains(&(name, None)) {
+                analysis.dead_fallbacks.push(name);
+            }
+            // Whether a workflow always has the same outcome does not depend on how it is reached.
+            let accepted_volume: i64 = self
+                .propagate(name, vec![initial_block.clone()], &mut |_, _, _| {})?
+                .iter()
+                .map(volume)
+                .sum();
+            if accepted_volume == volume(initial_block) {
+                analysis.always_accepting.push(name);
+            } else if accepted_volume == 0 {
+                analysis.always_rejecting.push(name);
+            }
+        }
+        return Ok(analysis);
+    }
+
+    // Returns an equivalent, simplified set of workflows for parts within |initial_block|.
+    // Repeatedly removes rules and fallbacks that never fire and rules that send parts to the
+    // same place as the fallback, replaces workflows with a fixed outcome by A or R, inlines
+    // workflows without rules and drops unreachable workflows.
+    fn simplified(&self, initial_block: &Block<'a>) -> Result<WorkflowEngine<'a>, String> {
+        let mut workflows = self.workflows.clone();
+        loop {
+            let engine = WorkflowEngine {
+                workflows: workflows.clone(),
+            };
+            let analysis = engine.analyze(initial_block)?;
+            let mut replacements: HashMap<&str, &str> = HashMap::new();
+            for name in &analysis.always_accepting {
+                replacements.insert(name, "A");
+            }
+            for name in &analysis.always_rejecting {
+                replacements.insert(name, "R");
+            }
+            for (name, workflow) in &workflows {
+                if workflow.rules.is_empty() && !replacements.contains_key(name) {
+                    replacements.insert(name, workflow.fallback);
+                }
+            }
+            // Keep the entry point, even if it is trivial.
+            replacements.remove("in");
+            // Replacements may lead to other replaced workflows, so follow them to the end.
+            let mut resolved: HashMap<&str, &str> = HashMap::new();
+            for name in replacements.keys() {
+                let mut target = *name;
+                for _ in 0..replacements.len() + 1 {
+                    target = match replacements.get(target) {
+                        Some(next) => next,
+                        None => break,
+                    };
+                }
+                if replacements.contains_key(target) {
+                    return Err(format!("workflows without rules cycle through {name}"));
+                }
+                resolved.insert(name, target);
+            }
+
+            let mut new_workflows: HashMap<&str, Workflow> = HashMap::new();
+            for (name, workflow) in &workflows {
+                if analysis.unreachable.contains(name) || replacements.contains_key(name) {
+                    continue;
+                }
+                let retarget = |target: &'a str| *resolved.get(target).unwrap_or(&target);
+                let mut new_workflow = Workflow {
+                    rules: Vec::new(),
+                    fallback: retarget(workflow.fallback),
+                };
+                for (i, rule) in workflow.rules.iter().enumerate() {
+                    if !analysis.dead_rules.contains(&(name, i)) {
+                        new_workflow.rules.push(Rule {
+                            target: retarget(rule.target),
+                            ..*rule
+                        });
+                    }
+                }
+                if analysis.dead_fallbacks.contains(name) {
+                    // The last remaining rule always fires, so it can be the fallback.
+                    if let Some(last_rule) = new_workflow.rules.pop() {
+                        new_workflow.fallback = last_rule.target;
+                    }
+                }
+                while new_workflow
+                    .rules
+                    .last()
+                    .is_some_and(|r| r.target == new_workflow.fallback)
+                {
+                    new_workflow.rules.pop();
+                }
+                new_workflows.insert(name, new_workflow);
+            }
+
+            let is_unchanged = new_workflows == workflows;
+            workflows = new_workflows;
+            if is_unchanged {
+                return Ok(WorkflowEngine { workflows });
+            }
+        }
+    }
+
+    fn format_workflows(&self) -> String {
+        let mut names: Vec<&str> = self.workflows.keys().copied().collect();
+        names.sort();
+        let mut text = String::new();
+        for name in names {
+            let workflow = &self.workflows[name];
+            let mut steps: Vec<String> = workflow.rules.iter().map(|r| r.to_string()).collect();
+            steps.push(workflow.fallback.to_string());
+            text += &format!("{name}{{{}}}\n", steps.join(","));
+        }
+        return text;
+    }
+}
+
+// Findings of the static analysis of a set of workflows, given the range of part ratings:
+// - rules that never fire, as (workflow, rule index),
+// - workflows whose fallback is never taken,
+// - workflows never reached from "in", and
+// - workflows that always accept or always reject, and can thus be inlined as A or R.
+struct WorkflowAnalysis<'a> {
+    dead_rules: Vec<(&'a str, usize)>,
+    dead_fallbacks: Vec<&'a str>,
+    unreachable: Vec<&'a str>,
+    always_accepting: Vec<&'a str>,
+    always_rejecting: Vec<&'a str>,
+}
+
+pub fn solve_part_1(text: &String) -> () {
+    let (engine, parts) = parse_input(text);
+    let mut accepted_rating_sum = 0;
+    for part in &parts {
+        match engine.evaluate(part) {
+            Ok((true, _)) => {
+                accepted_rating_sum += part.iter().map(|(_, v)| v).sum::<i64>();
+            }
+            Ok((false, _)) => {}
+            Err(e) => {
+                panic!("bad workflows: {}", e);
+            }
+        }
+    }
 
     println!("Sum of accepted part ratings: {accepted_rating_sum}");
     println!("Expected puzzle answer:       374873");
 }
 
 pub fn solve_part_2(text: &String) -> () {
-    let (workflows, _) = parse_input(text);
-    let initial_blocks = vec![((1, 4000), (1, 4000), (1, 4000), (1, 4000))];
-    let accepted_volume = get_accepted_blocks(workflows, initial_blocks)
-        .iter()
-        .fold(0, |sum, block| sum + volume(*block));
+    let (engine, parts) = parse_input(text);
+    let initial_block = full_rating_block(&engine, &parts);
+    let accepted_volume: i64 = match engine.accepted_blocks(vec![initial_block]) {
+        Ok(blocks) => blocks.iter().map(volume).sum(),
+        Err(e) => {
+            panic!("bad workflows: {}", e);
+        }
+    };
 
     println!("Number of valid rating combos: {accepted_volume}");
     println!("Expected puzzle answer:        122112157518711");
 }
 
-fn parse_input(
-    input: &str,
-) -> (
-    HashMap<&str, (Vec<(char, char, i64, &str)>, &str)>,
-    Vec<(i64, i64, i64, i64)>,
-) {
+// Returns the block of all ratings from 1 to 4000, for all categories of the parts and rules.
+fn full_rating_block<'a>(engine: &WorkflowEngine<'a>, parts: &Vec<Part<'a>>) -> Block<'a> {
+    let rule_categories = engine
+        .workflows
+        .values()
+        .flat_map(|w| w.rules.iter().map(|r| r.category));
+    let part_categories = parts.iter().flat_map(|p| p.iter().map(|(c, _)| *c));
+    return rule_categories
+        .chain(part_categories)
+        .map(|category| (category, (1, 4000)))
+        .collect();
+}
+
+// Prints the workflows and rules each part passes through.
+pub fn print_part_traces(text: &String) -> () {
+    let (engine, parts) = parse_input(text);
+    for part in &parts {
+        let ratings: Vec<String> = part.iter().map(|(c, v)| format!("{c}={v}")).collect();
+        match engine.evaluate(part) {
+            Ok((is_accepted, trace)) => {
+                let trace_text = engine.format_trace(&trace, is_accepted);
+                println!("{{{}}}: {trace_text}", ratings.join(","));
+            }
+            Err(e) => println!("{{{}}}: {e}", ratings.join(",")),
+        }
+    }
+}
+
+// Prints rules that never fire, unreachable workflows, workflows with a fixed outcome, and an
+// equivalent simplified set of workflows.
+pub fn print_workflow_analysis(text: &String) -> () {
+    let (engine, parts) = parse_input(text);
+    let initial_block = full_rating_block(&engine, &parts);
+    let (analysis, simplified) = match engine
+        .analyze(&initial_block)
+        .and_then(|analysis| Ok((analysis, engine.simplified(&initial_block)?)))
+    {
+        Ok(result) => result,
+        Err(e) => {
+            panic!("bad workflows: {}", e);
+        }
+    };
+
+    println!("Rules that never fire:");
+    for (name, i) in &analysis.dead_rules {
+        println!("  {name}: {}", engine.workflows[name].rules[*i]);
+    }
+    println!(
+        "Fallbacks never taken: {}",
+        analysis.dead_fallbacks.join(" ")
+    );
+    println!("Unreachable workflows: {}", analysis.unreachable.join(" "));
+    println!(
+        "Always accepting:      {}",
+        analysis.always_accepting.join(" ")
+    );
+    println!(
+        "Always rejecting:      {}",
+        analysis.always_rejecting.join(" ")
+    );
+
+    let accepted_volume = |e: &WorkflowEngine| -> i64 {
+        return e
+            .accepted_blocks(vec![initial_block.clone()])
+            .unwrap()
+            .iter()
+            .map(volume)
+            .sum();
+    };
+    let num_rules = |e: &WorkflowEngine| -> usize {
+        return e.workflows.values().map(|w| w.rules.len()).sum();
+    };
+    println!(
+        "Simplified from {} to {} workflows and from {} to {} rules:",
+        engine.workflows.len(),
+        simplified.workflows.len(),
+        num_rules(&engine),
+        num_rules(&simplified)
+    );
+    print!("{}", simplified.format_workflows());
+    // The simplified workflows must accept exactly the same parts.
+    assert_eq!(accepted_volume(&engine), accepted_volume(&simplified));
+}
+
+fn parse_input(input: &str) -> (WorkflowEngine<'_>, Vec<Part<'_>>) {
     let (workflows_text, parts_text) = input.split_once("\n\n").unwrap();
 
-    let workflows: HashMap<&str, (Vec<(char, char, i64, &str)>, &str)> =
-        match all_consuming(separated_list1(
-            line_ending,
-            tuple((
-                alpha1::<_, Error<_>>,
-                delimited(
-                    tag("{"),
-                    tuple((
-                        many0(terminated(
-                            tuple((
-                                one_of("xmas"),
-                                one_of("<>"),
-                                i64,
-                                preceded(tag(":"), alpha1),
-                            )),
-                            tag(","),
-                        )),
-                        alpha1,
-                    )),
-                    tag("}"),
-                ),
+    let rule = map(
+        tuple((
+            alpha1::<_, Error<_>>,
+            alt((
+                map(tag("<="), |_| Op::LessOrEqual),
+                map(tag(">="), |_| Op::GreaterOrEqual),
+                map(tag("=="), |_| Op::Equal),
+                map(tag("<"), |_| Op::Less),
+                map(tag(">"), |_| Op::Greater),
             )),
-        ))(workflows_text)
-        {
-            Err(e) => {
-                panic!("bad input: {}", e);
-            }
-            Ok((_, result)) => HashMap::from_iter(result),
-        };
+            i64,
+            preceded(tag(":"), alpha1),
+        )),
+        |(category, op, value, target)| Rule {
+            category,
+            op,
+            value,
+            target,
+        },
+    );
+    let workflows: HashMap<&str, Workflow> = match all_consuming(separated_list1(
+        line_ending,
+        tuple((
+            alpha1,
+            delimited(
+                tag("{"),
+                map(
+                    tuple((many0(terminated(rule, tag(","))), alpha1)),
+                    |(rules, fallback)| Workflow { rules, fallback },
+                ),
+                tag("}"),
+            ),
+        )),
+    ))(workflows_text)
+    {
+        Err(e) => {
+            panic!("bad input: {}", e);
+        }
+        Ok((_, result)) => HashMap::from_iter(result),
+    };
 
     let parts = match all_consuming(separated_list1(
         line_ending,
         delimited(
             tag::<_, _, Error<_>>("{"),
-            tuple((
-                preceded(tag("x="), i64),
-                preceded(tag(",m="), i64),
-                preceded(tag(",a="), i64),
-                preceded(tag(",s="), i64),
-            )),
+            separated_list1(tag(","), separated_pair(alpha1, tag("="), i64)),
             tag("}"),
         ),
     ))(parts_text)
@@ -101,85 +575,5 @@ fn parse_input(
         Ok((_, result)) => result,
     };
 
-    return (workflows, parts);
-}
-
-fn get_accepted_blocks(
-    workflows: HashMap<&str, (Vec<(char, char, i64, &str)>, &str)>,
-    initial_blocks: Vec<Block>,
-) -> Vec<Block> {
-    // Approach:
-    // Start with the initial blocks.
-    // Each rule will split the block along some axis, creating two (potentially empty) new blocks.
-    // Keep applying rules and splitting blocks, sending them to different workflows.
-    // Discard any empty blocks encountered.
-    // When all blocks are accepted or rejected, stop and return the accepted blocks.
-
-    // Tests a rule against a block and returns the passing/failing ranges as a pair (pass,fail).
-    fn apply_rule(b: Block, field: char, op: char, limit: i64) -> (Option<Block>, Option<Block>) {
-        let (offset_lo, offset_hi, lo_pass) = match op {
-            '<' => (-1, 0, true),
-            '>' => (0, 1, false),
-            _ => panic!("bad operator symbol {op}"),
-        };
-        let (b_lo, b_hi) = match field {
-            'x' => (
-                ((b.0 .0, cmp::min(b.0 .1, limit + offset_lo)), b.1, b.2, b.3),
-                ((cmp::max(b.0 .0, limit + offset_hi), b.0 .1), b.1, b.2, b.3),
-            ),
-            'm' => (
-                (b.0, (b.1 .0, cmp::min(b.1 .1, limit + offset_lo)), b.2, b.3),
-                (b.0, (cmp::max(b.1 .0, limit + offset_hi), b.1 .1), b.2, b.3),
-            ),
-            'a' => (
-                (b.0, b.1, (b.2 .0, cmp::min(b.2 .1, limit + offset_lo)), b.3),
-                (b.0, b.1, (cmp::max(b.2 .0, limit + offset_hi), b.2 .1), b.3),
-            ),
-            's' => (
-                (b.0, b.1, b.2, (b.3 .0, cmp::min(b.3 .1, limit + offset_lo))),
-                (b.0, b.1, b.2, (cmp::max(b.3 .0, limit + offset_hi), b.3 .1)),
-            ),
-            _ => panic!("bad part field {field}"),
-        };
-        let volume_or_none = |(x, m, a, s): Block| {
-            if x.1 >= x.0 && m.1 >= m.0 && a.1 >= a.0 && s.1 >= s.0 {
-                return Some((x, m, a, s));
-            }
-            return None;
-        };
-        if lo_pass {
-            return (volume_or_none(b_lo), volume_or_none(b_hi));
-        } else {
-            return (volume_or_none(b_hi), volume_or_none(b_lo));
-        }
-    }
-
-    let mut stack: Vec<(&str, Block)> = Vec::from_iter(initial_blocks.iter().map(|b| ("in", *b)));
-    let mut accepted_blocks: Vec<Block> = Vec::new();
-    while let Some((label, block)) = stack.pop() {
-        if label == "R" {
-            continue;
-        }
-        if label == "A" {
-            accepted_blocks.push(block);
-            continue;
-        }
-        let workflow = workflows.get(label).unwrap();
-        // Apply each rule, splitting the block of values into sub-blocks that either pass or fail each rule.
-        let mut remaining_block = Some(block);
-        for rule in &workflow.0 {
-            if remaining_block.is_none() {
-                break;
-            }
-            let (b_pass, b_fail) = apply_rule(remaining_block.unwrap(), rule.0, rule.1, rule.2);
-            if b_pass.is_some() {
-                stack.push((rule.3, b_pass.unwrap()));
-            }
-            remaining_block = b_fail;
-        }
-        if remaining_block.is_some() {
-            stack.push((workflow.1, remaining_block.unwrap()));
-        }
-    }
-    return accepted_blocks;
+    return (WorkflowEngine { workflows }, parts);
 }
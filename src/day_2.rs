@@ -1,99 +1,188 @@
 use nom::{
-    branch::alt,
     bytes::complete::tag,
-    character::complete::{alpha1, i32, space1},
-    combinator::{all_consuming, map_res},
+    character::complete::{alpha1, space1, u32, u64},
+    combinator::all_consuming,
+    error::Error,
     multi::separated_list1,
     sequence::{delimited, pair, separated_pair},
-    Err, IResult,
+    IResult,
 };
 
-use std::cmp;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet};
 
-// https://doc.rust-lang.org/reference/attributes/derive.html
-// Needed to use the enum as a key type in HashMap.
-#[derive(Eq, Hash, PartialEq)]
-enum Color {
-    Red,
-    Green,
-    Blue,
+// A number of cubes of each color, for a draw or for the contents of a bag. Colors are whatever
+// names appear in the input.
+type Cubes = BTreeMap<String, u64>;
+
+// The bag of part 1, and the colors whose counts are multiplied for the power in part 2.
+const PUZZLE_BAG: [(&str, u64); 3] = [("red", 12), ("green", 13), ("blue", 14)];
+
+struct Game {
+    id: u32,
+    draws: Vec<Cubes>,
 }
 
-fn to_color(input: &str) -> Result<Color, Err<&str>> {
-    match input {
-        "red" => Ok(Color::Red),
-        "green" => Ok(Color::Green),
-        "blue" => Ok(Color::Blue),
-        _ => Err(Err::Failure("boo")),
+impl Game {
+    // The fewest cubes of each color the bag could have held.
+    fn minimal_bag(&self) -> Cubes {
+        let mut bag = Cubes::new();
+        for draw in &self.draws {
+            for (color, n) in draw {
+                let count = bag.entry(color.clone()).or_insert(0);
+                *count = (*count).max(*n);
+            }
+        }
+        return bag;
     }
-}
 
-// Example: "14 green" => (14, Color::Green)
-fn parse_cube(input: &str) -> IResult<&str, (i32, Color)> {
-    separated_pair(i32, space1, map_res(alpha1, to_color))(input)
+    // The cubes of each color missing from the bag for the game to be possible.
+    fn shortfall(&self, bag: &Cubes) -> Cubes {
+        return self
+            .minimal_bag()
+            .into_iter()
+            .filter_map(|(color, n)| {
+                let available = *bag.get(&color).unwrap_or(&0);
+                return (n > available).then(|| (color, n - available));
+            })
+            .collect();
+    }
+
+    fn is_feasible(&self, bag: &Cubes) -> bool {
+        return self.shortfall(bag).is_empty();
+    }
 }
 
-// Example: "1 green, 2 blue; 5 red" => [(1, Color::Green), (2, Color::blue), (5, Color::Red)]
-// This ignores the distinction between draws within and between sets of a game.
-// The problem does not use this extra "substructure" anyway.
-fn parse_game(input: &str) -> IResult<&str, Vec<(i32, Color)>> {
-    separated_list1(alt((tag(", "), tag("; "))), parse_cube)(input)
+// Example: "1 green, 2 blue" => {"blue": 2, "green": 1}
+// A color named more than once in a draw counts all its cubes.
+fn parse_draw(input: &str) -> IResult<&str, Cubes> {
+    let (rest, cubes) = separated_list1(tag(", "), separated_pair(u64, space1, alpha1))(input)?;
+    let mut draw = Cubes::new();
+    for (n, color) in cubes {
+        *draw.entry(color.to_string()).or_insert(0) += n;
+    }
+    return Ok((rest, draw));
 }
 
-// Example: "Game 10: 1 green, 2 blue; 5 red" => (10, [(1, Color::Green), (2, Color::blue), (5, Color::Red)])
-fn parse_line(input: &str) -> (i32, Vec<(i32, Color)>) {
-    match all_consuming(pair(delimited(tag("Game "), i32, tag(": ")), parse_game))(input) {
+// Example: "Game 10: 1 green, 2 blue; 5 red" => Game 10 with draws [{blue: 2, green: 1}, {red: 5}]
+fn parse_line(input: &str) -> Game {
+    match all_consuming(pair(
+        delimited(tag("Game "), u32::<_, Error<_>>, tag(": ")),
+        separated_list1(tag("; "), parse_draw),
+    ))(input)
+    {
         Err(e) => {
             panic!("bad input: {}", e);
         }
         Ok((_, (id, draws))) => {
-            return (id, draws);
+            return Game { id, draws };
         }
     }
 }
 
+fn parse_games(text: &str) -> Vec<Game> {
+    return text.split("\n").map(parse_line).collect();
+}
+
+fn puzzle_bag() -> Cubes {
+    return PUZZLE_BAG
+        .iter()
+        .map(|(color, n)| (color.to_string(), *n))
+        .collect();
+}
+
+// The games that are not feasible with the bag, but are with |n| more cubes of |color|.
+fn feasible_after_adding<'a>(
+    games: &'a Vec<Game>,
+    bag: &Cubes,
+    color: &str,
+    n: u64,
+) -> Vec<&'a Game> {
+    let mut larger_bag = bag.clone();
+    *larger_bag.entry(color.to_string()).or_insert(0) += n;
+    return games
+        .iter()
+        .filter(|game| !game.is_feasible(bag) && game.is_feasible(&larger_bag))
+        .collect();
+}
+
+fn format_cubes(cubes: &Cubes) -> String {
+    let counts: Vec<String> = cubes
+        .iter()
+        .map(|(color, n)| format!("{n} {color}"))
+        .collect();
+    return counts.join(", ");
+}
+
 pub fn solve_part_1(text: &String) -> () {
-    let lines: Vec<String> = text.split("\n").map(|s| s.to_string()).collect();
-    let mut id_sum = 0;
-    for line in lines {
-        let (id, draws) = parse_line(&line);
-        let mut max_map: HashMap<Color, i32> = HashMap::new();
-        for (num, color) in draws {
-            max_map
-                .entry(color)
-                .and_modify(|v| *v = cmp::max(*v, num))
-                .or_insert(num);
-        }
-        if max_map.get(&Color::Red).unwrap_or(&0) <= &12
-            && max_map.get(&Color::Green).unwrap_or(&0) <= &13
-            && max_map.get(&Color::Blue).unwrap_or(&0) <= &14
-        {
-            id_sum += id;
-        }
-    }
+    let bag = puzzle_bag();
+    let id_sum: u32 = parse_games(text)
+        .iter()
+        .filter(|game| game.is_feasible(&bag))
+        .map(|game| game.id)
+        .sum();
 
     println!("Sum of valid game IDs   {}", id_sum);
     println!("Expected puzzle answer: 2239");
 }
 
 pub fn solve_part_2(text: &String) -> () {
-    let lines: Vec<String> = text.split("\n").map(|s| s.to_string()).collect();
     let mut power_sum = 0;
-    for line in lines {
-        let (_, draws) = parse_line(&line);
-        let mut max_map: HashMap<Color, i32> = HashMap::new();
-        for (num, color) in draws {
-            max_map
-                .entry(color)
-                .and_modify(|v| *v = cmp::max(*v, num))
-                .or_insert(num);
-        }
-        power_sum += max_map.get(&Color::Red).unwrap_or(&0)
-            * max_map.get(&Color::Green).unwrap_or(&0)
-            * max_map.get(&Color::Blue).unwrap_or(&0);
+    for game in parse_games(text) {
+        let minimal_bag = game.minimal_bag();
+        power_sum += PUZZLE_BAG
+            .iter()
+            .map(|(color, _)| *minimal_bag.get(*color).unwrap_or(&0))
+            .product::<u64>();
     }
 
     println!("Sum of valid game IDs   {}", power_sum);
     println!("Expected puzzle answer: 83435");
 }
+
+// Prints the minimal bag of each game and what the part 1 bag lacks for it, and then, for each
+// color, which games become feasible when adding cubes of only that color to the part 1 bag.
+pub fn print_bag_analysis(text: &String) -> () {
+    let games = parse_games(text);
+    let bag = puzzle_bag();
+    let mut colors: BTreeSet<String> = bag.keys().cloned().collect();
+    for game in &games {
+        let shortfall = game.shortfall(&bag);
+        match shortfall.is_empty() {
+            true => println!(
+                "Game {}: needs {}, feasible",
+                game.id,
+                format_cubes(&game.minimal_bag())
+            ),
+            false => println!(
+                "Game {}: needs {}, short of {}",
+                game.id,
+                format_cubes(&game.minimal_bag()),
+                format_cubes(&shortfall)
+            ),
+        }
+        colors.extend(game.minimal_bag().into_keys());
+    }
+
+    println!("Bag: {}", format_cubes(&bag));
+    for color in &colors {
+        // Only games short of this color alone can be fixed with it, and those become feasible
+        // once as many cubes as they lack are added.
+        let mut amounts: BTreeSet<u64> = BTreeSet::new();
+        for game in &games {
+            let shortfall = game.shortfall(&bag);
+            if let (1, Some(n)) = (shortfall.len(), shortfall.get(color)) {
+                amounts.insert(*n);
+            }
+        }
+        for n in amounts {
+            let ids: Vec<String> = feasible_after_adding(&games, &bag, color, n)
+                .iter()
+                .map(|game| game.id.to_string())
+                .collect();
+            println!(
+                "  Adding {n} {color} makes games {} feasible",
+                ids.join(", ")
+            );
+        }
+    }
+}
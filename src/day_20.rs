@@ -8,125 +8,557 @@ use nom::{
 };
 
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::collections::VecDeque;
+use std::fmt;
+use std::fs;
 
-pub fn solve_part_1(text: &String) -> () {
-    let (low_pulses, high_pulses, _) = press_button_collect_data(text);
-    let answer = low_pulses * high_pulses;
+// Module name to module type and list of destination module names.
+type ModuleMap<'a> = HashMap<&'a str, (Option<char>, Vec<&'a str>)>;
 
-    println!("Product of num high/low pulses: {answer}");
-    println!("Expected puzzle answer:         821985143");
+#[derive(Clone, Copy)]
+struct Pulse<'a> {
+    source: &'a str,
+    destination: &'a str,
+    is_low: bool,
 }
 
-pub fn solve_part_2(text: &String) -> () {
-    let (_, _, key_press_periods) = press_button_collect_data(text);
-    let answer = key_press_periods
-        .iter()
-        .fold(1, |prod, period| prod * period);
+impl fmt::Display for Pulse<'_> {
+    // Formats the pulse like the puzzle text, e.g. "button -low-> broadcaster".
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let level = match self.is_low {
+            true => "low",
+            false => "high",
+        };
+        return write!(f, "{} -{}-> {}", self.source, level, self.destination);
+    }
+}
 
-    println!("Button presses until rx turned on: {answer}");
-    println!("Expected puzzle answer:            240853834793347");
+// The memory of all flip-flops and conjunctions.
+// Flip-flops are true while off, and conjunctions remember true for low pulses.
+#[derive(Clone, PartialEq)]
+struct CircuitState<'a> {
+    flipflop_state: HashMap<&'a str, bool>,
+    conj_state: HashMap<&'a str, HashMap<&'a str, bool>>,
 }
 
-// Presses the button repeatedly and returns a tuple of:
-// - the number of low pulses observed within the first 1000 button presses,
-// - the number of high pulses observed within the first 1000 button presses, and
-// - the (relatively prime) number of presses required to observe a low pulse from four key nodes for part two.
-fn press_button_collect_data(text: &str) -> (i64, i64, Vec<i64>) {
-    let module_map: HashMap<&str, (Option<char>, Vec<&str>)> = parse_modules(text);
-    let terminal_node = (None, vec![]);
-    let mut flipflop_state: HashMap<&str, bool> = HashMap::new();
-    let mut conj_state: HashMap<&str, HashMap<&str, bool>> = HashMap::new();
-    for (name, (type_symbol, destinations)) in &module_map {
-        if *type_symbol == Some('%') {
-            flipflop_state.insert(name, true);
-        }
-        for dest in destinations {
-            if module_map.get(dest).unwrap_or(&terminal_node).0 == Some('&') {
-                conj_state.entry(dest).or_default().insert(name, true);
+impl<'a> CircuitState<'a> {
+    // The memory as bits in a fixed order, so that states can be hashed.
+    fn bits(&self) -> Vec<bool> {
+        let mut flipflops: Vec<(&&str, &bool)> = self.flipflop_state.iter().collect();
+        flipflops.sort();
+        let mut memories: Vec<(&&str, &&str, &bool)> = self
+            .conj_state
+            .iter()
+            .flat_map(|(conj, inputs)| inputs.iter().map(move |(input, b)| (conj, input, b)))
+            .collect();
+        memories.sort();
+        return flipflops
+            .iter()
+            .map(|(_, b)| **b)
+            .chain(memories.iter().map(|(_, _, b)| **b))
+            .collect();
+    }
+}
+
+// A pulse circuit simulator, pressing the button one press at a time.
+// Pulses on subscribed wires are collected with the press they were sent in, and if tracing is
+// enabled, so are all pulses.
+struct Circuit<'a> {
+    module_map: &'a ModuleMap<'a>,
+    state: CircuitState<'a>,
+    num_presses: i64,
+    subscriptions: HashSet<(&'a str, &'a str)>,
+    subscribed_pulses: Vec<(i64, Pulse<'a>)>,
+    trace: Option<Vec<(i64, Pulse<'a>)>>,
+}
+
+impl<'a> Circuit<'a> {
+    fn new(module_map: &'a ModuleMap<'a>) -> Circuit<'a> {
+        let terminal_node = (None, vec![]);
+        let mut flipflop_state: HashMap<&str, bool> = HashMap::new();
+        let mut conj_state: HashMap<&str, HashMap<&str, bool>> = HashMap::new();
+        for (name, (type_symbol, destinations)) in module_map {
+            if *type_symbol == Some('%') {
+                flipflop_state.insert(name, true);
+            }
+            for dest in destinations {
+                if module_map.get(dest).unwrap_or(&terminal_node).0 == Some('&') {
+                    conj_state.entry(dest).or_default().insert(name, true);
+                }
             }
         }
+        return Circuit {
+            module_map,
+            state: CircuitState {
+                flipflop_state,
+                conj_state,
+            },
+            num_presses: 0,
+            subscriptions: HashSet::new(),
+            subscribed_pulses: Vec::new(),
+            trace: None,
+        };
     }
 
-    // Press the button until we know:
-    // - the number of low and high pulses after 1000 presses, and
-    // - the number of presses needed to get a low pulse out of each key nodes for part two.
-    let mut button_press_count: i64 = 0;
-    let mut low_pulse_counter: i64 = 0;
-    let mut high_pulse_counter: i64 = 0;
-    let mut key_press_periods: HashMap<&str, i64> = HashMap::new();
-    let key_nodes = vec!["th", "nt", "ff", "zs"];
-    while button_press_count < 1000 || key_press_periods.len() < key_nodes.len() {
+    // Collects the pulses sent from |source| to |destination| from now on.
+    fn subscribe(&mut self, source: &'a str, destination: &'a str) -> () {
+        self.subscriptions.insert((source, destination));
+    }
+
+    // Returns the pulses on subscribed wires since the last call, with their press numbers.
+    fn take_subscribed_pulses(&mut self) -> Vec<(i64, Pulse<'a>)> {
+        return std::mem::take(&mut self.subscribed_pulses);
+    }
+
+    // Starts recording all pulses, with their press numbers.
+    fn enable_trace(&mut self) -> () {
+        self.trace.get_or_insert_with(Vec::new);
+    }
+
+    fn take_trace(&mut self) -> Vec<(i64, Pulse<'a>)> {
+        return self.trace.take().unwrap_or_default();
+    }
+
+    fn snapshot(&self) -> (CircuitState<'a>, i64) {
+        return (self.state.clone(), self.num_presses);
+    }
+
+    fn restore(&mut self, (state, num_presses): (CircuitState<'a>, i64)) -> () {
+        self.state = state;
+        self.num_presses = num_presses;
+    }
+
+    fn are_flipflops_off(&self) -> bool {
+        return self.state.flipflop_state.values().all(|is_off| *is_off);
+    }
+
+    // Presses the button once, and returns the number of low and high pulses sent, including
+    // the one from the button to the broadcaster.
+    fn press_button(&mut self) -> (i64, i64) {
+        self.num_presses += 1;
+        let mut num_low_pulses = 0;
+        let mut num_high_pulses = 0;
+        let terminal_node = (None, vec![]);
         // Simulate the pulse propagation with a queue, enqueueing any module that receives a new pulse.
-        button_press_count += 1;
-        if button_press_count <= 1000 {
-            low_pulse_counter += 1;
-        }
-        let mut queue: VecDeque<(&str, &str, bool)> =
-            VecDeque::from([("button", "broadcaster", true)]);
-        while let Some((input_name, name, low_pulse)) = queue.pop_front() {
-            let (type_symbol, destinations) = module_map.get(name).unwrap_or(&terminal_node);
+        let mut queue: VecDeque<Pulse> = VecDeque::from([Pulse {
+            source: "button",
+            destination: "broadcaster",
+            is_low: true,
+        }]);
+        while let Some(pulse) = queue.pop_front() {
+            if pulse.is_low {
+                num_low_pulses += 1;
+            } else {
+                num_high_pulses += 1;
+            }
+            if self
+                .subscriptions
+                .contains(&(pulse.source, pulse.destination))
+            {
+                self.subscribed_pulses.push((self.num_presses, pulse));
+            }
+            if let Some(trace) = &mut self.trace {
+                trace.push((self.num_presses, pulse));
+            }
+            let name = pulse.destination;
+            let (type_symbol, destinations) = self.module_map.get(name).unwrap_or(&terminal_node);
 
             // Update state and compute module output.
             let new_pulse: Option<bool>;
             match type_symbol {
                 Some('%') => {
                     // Flip-flip.
-                    if low_pulse {
-                        flipflop_state.entry(name).and_modify(|b| {
-                            *b = !*b;
-                        });
-                        new_pulse = Some(*flipflop_state.get(name).unwrap());
+                    if pulse.is_low {
+                        let is_off = self.state.flipflop_state.get_mut(name).unwrap();
+                        *is_off = !*is_off;
+                        new_pulse = Some(*is_off);
                     } else {
                         new_pulse = None;
                     }
                 }
                 Some('&') => {
                     // Conjugation.
-                    let inputs = conj_state.get_mut(name).unwrap();
-                    inputs.insert(input_name, low_pulse);
+                    let inputs = self.state.conj_state.get_mut(name).unwrap();
+                    inputs.insert(pulse.source, pulse.is_low);
                     let is_any_input_low = inputs.values().any(|b| *b);
                     new_pulse = Some(!is_any_input_low);
                 }
                 None => {
                     // Broadcaster or debug output.
-                    new_pulse = Some(low_pulse);
+                    new_pulse = Some(pulse.is_low);
                 }
                 _ => {
                     panic!("unexpected type symbol: {}", type_symbol.unwrap());
                 }
             }
-            match new_pulse {
-                Some(pulse) => {
-                    if button_press_count <= 1000 {
-                        if pulse {
-                            low_pulse_counter += destinations.len() as i64;
-                        } else {
-                            high_pulse_counter += destinations.len() as i64;
-                        }
-                    }
-                    if pulse && key_nodes.contains(&name) {
-                        key_press_periods.entry(name).or_insert(button_press_count);
-                    }
-                    // Propagate to destination modules.
-                    for dest in destinations {
-                        queue.push_back((name, dest, pulse));
+            if let Some(is_low) = new_pulse {
+                // Propagate to destination modules.
+                for dest in destinations {
+                    queue.push_back(Pulse {
+                        source: name,
+                        destination: dest,
+                        is_low,
+                    });
+                }
+            }
+        }
+        return (num_low_pulses, num_high_pulses);
+    }
+}
+
+pub fn solve_part_1(text: &String) -> () {
+    let module_map = parse_modules(text);
+    let mut circuit = Circuit::new(&module_map);
+    let mut low_pulse_counter: i64 = 0;
+    let mut high_pulse_counter: i64 = 0;
+    for _ in 0..1000 {
+        let (num_low_pulses, num_high_pulses) = circuit.press_button();
+        low_pulse_counter += num_low_pulses;
+        high_pulse_counter += num_high_pulses;
+    }
+    let answer = low_pulse_counter * high_pulse_counter;
+
+    println!("Product of num high/low pulses: {answer}");
+    println!("Expected puzzle answer:         821985143");
+}
+
+pub fn solve_part_2(text: &String) -> () {
+    let module_map = parse_modules(text);
+    let answer = match presses_until_rx_low(&module_map) {
+        Ok(presses) => presses,
+        Err(e) => {
+            panic!("unable to determine presses until rx turns on: {}", e);
+        }
+    };
+
+    println!("Button presses until rx turned on: {answer}");
+    println!("Expected puzzle answer:            240853834793347");
+}
+
+// Prints what the part 2 analysis sees in the circuit: the module feeding rx, the first press and
+// period of each of its inputs, the pulse trace of the first button press, and whether the
+// flip-flops return to all off within the analysis limit.
+pub fn print_circuit_inspection(text: &String) -> () {
+    let module_map = parse_modules(text);
+    match find_key_nodes(&module_map) {
+        Ok((feeder, key_nodes)) => {
+            println!("rx is fed by {feeder}, with inputs:");
+            match key_node_periods(&module_map, feeder, &key_nodes) {
+                Ok(periods) => {
+                    for (key_node, first, period) in periods {
+                        println!(
+                            "  {key_node}: first high pulse at press {first}, period {period}"
+                        );
                     }
                 }
-                _ => {}
+                Err(e) => println!("  {e}"),
             }
         }
+        Err(e) => println!("{e}"),
     }
 
-    return (
-        low_pulse_counter,
-        high_pulse_counter,
-        key_press_periods.into_values().collect(),
-    );
+    let mut circuit = Circuit::new(&module_map);
+    let initial = circuit.snapshot();
+    circuit.enable_trace();
+    circuit.press_button();
+    println!("Pulses of the first button press:");
+    for (_, pulse) in circuit.take_trace() {
+        println!("  {pulse}");
+    }
+    circuit.restore(initial);
+    loop {
+        circuit.press_button();
+        if circuit.are_flipflops_off() {
+            println!(
+                "All flip-flops are off again after {} presses",
+                circuit.num_presses
+            );
+            break;
+        }
+        if circuit.num_presses >= MAX_ANALYSIS_PRESSES {
+            println!("The flip-flops are not all off again within {MAX_ANALYSIS_PRESSES} presses");
+            break;
+        }
+    }
+}
+
+// Returns the modules that have |target| downstream, excluding the broadcaster.
+fn upstream_modules<'a>(module_map: &ModuleMap<'a>, target: &'a str) -> HashSet<&'a str> {
+    let mut upstream: HashSet<&str> = HashSet::from([target]);
+    let mut to_visit: Vec<&str> = vec![target];
+    while let Some(name) = to_visit.pop() {
+        for (source, (_, destinations)) in module_map {
+            if *source != "broadcaster" && destinations.contains(&name) && upstream.insert(source) {
+                to_visit.push(source);
+            }
+        }
+    }
+    return upstream;
+}
+
+// Renders the module network in Graphviz DOT format, drawing flip-flops as boxes, conjunctions
+// as diamonds and the broadcaster as a double circle.
+// If |group_counters| is set and the key nodes feeding rx are driven by disjoint sub-networks,
+// each sub-network is drawn as a cluster.
+fn format_modules_dot(module_map: &ModuleMap, group_counters: bool) -> String {
+    let mut clusters: Vec<(&str, HashSet<&str>)> = Vec::new();
+    if group_counters {
+        if let Ok((_, key_nodes)) = find_key_nodes(module_map) {
+            clusters = key_nodes
+                .iter()
+                .map(|k| (*k, upstream_modules(module_map, k)))
+                .collect();
+            let num_clustered: usize = clusters.iter().map(|(_, c)| c.len()).sum();
+            let all_clustered: HashSet<&str> =
+                clusters.iter().flat_map(|(_, c)| c).copied().collect();
+            if all_clustered.len() != num_clustered {
+                // The sub-networks overlap, so they are not independent counters.
+                clusters.clear();
+            }
+        }
+    }
+    let node_line = |name: &str| -> String {
+        let shape = match module_map.get(name).map(|m| m.0) {
+            Some(Some('%')) => "box",
+            Some(Some('&')) => "diamond",
+            Some(None) => "doublecircle",
+            _ => "plaintext",
+        };
+        return format!("{name} [shape={shape}];\n");
+    };
+
+    let mut dot = String::from("digraph modules {\n");
+    for (key_node, cluster) in &clusters {
+        dot += &format!("  subgraph cluster_{key_node} {{\n");
+        dot += &format!("    label=\"{key_node}\";\n");
+        let mut names: Vec<&&str> = cluster.iter().collect();
+        names.sort();
+        for name in names {
+            dot += &format!("    {}", node_line(name));
+        }
+        dot += "  }\n";
+    }
+    let mut names: Vec<&str> = module_map.keys().copied().collect();
+    for (_, destinations) in module_map.values() {
+        names.extend(destinations.iter().filter(|d| !module_map.contains_key(*d)));
+    }
+    names.sort();
+    names.dedup();
+    for name in names {
+        if !clusters.iter().any(|(_, c)| c.contains(name)) {
+            dot += &format!("  {}", node_line(name));
+        }
+    }
+    let mut sources: Vec<&&str> = module_map.keys().collect();
+    sources.sort();
+    for source in sources {
+        for dest in &module_map[source].1 {
+            dot += &format!("  {source} -> {dest};\n");
+        }
+    }
+    dot += "}\n";
+    return dot;
+}
+
+fn write_modules_dot(text: &str, group_counters: bool) -> () {
+    let module_map = parse_modules(text);
+    let path = "day_20.dot";
+    fs::write(path, format_modules_dot(&module_map, group_counters))
+        .expect("unable to write DOT file");
+    println!("Wrote module network to {path}");
+}
+
+pub fn write_dot(text: &String) -> () {
+    write_modules_dot(text, /*group_counters*/ false);
+}
+
+pub fn write_dot_with_counters(text: &String) -> () {
+    write_modules_dot(text, /*group_counters*/ true);
+}
+
+// The number of presses to watch the key nodes for, before giving up on finding their periods.
+const MAX_ANALYSIS_PRESSES: i64 = 100_000;
+// Networks with at most this many flip-flops are simulated until rx receives a low pulse,
+// if the analysis fails on them.
+const MAX_BRUTE_FORCE_FLIPFLOPS: usize = 20;
+
+// Finds the conjunction feeding rx and its inputs, the key nodes. See presses_until_rx_low.
+fn find_key_nodes<'a>(module_map: &ModuleMap<'a>) -> Result<(&'a str, Vec<&'a str>), String> {
+    let feeders: Vec<&str> = module_map
+        .iter()
+        .filter(|(_, (_, destinations))| destinations.contains(&"rx"))
+        .map(|(name, _)| *name)
+        .collect();
+    let feeder = match feeders.as_slice() {
+        [] => {
+            return Err("no module sends pulses to rx".to_string());
+        }
+        [feeder] if module_map[feeder].0 != Some('&') => {
+            return Err(format!("rx is fed by {feeder}, which is not a conjunction"));
+        }
+        [feeder] => *feeder,
+        _ => {
+            return Err(format!(
+                "rx is fed by several modules: {}",
+                feeders.join(", ")
+            ));
+        }
+    };
+    let mut key_nodes: Vec<&str> = module_map
+        .iter()
+        .filter(|(_, (_, destinations))| destinations.contains(&feeder))
+        .map(|(name, _)| *name)
+        .collect();
+    key_nodes.sort();
+    return Ok((feeder, key_nodes));
+}
+
+// Returns the first press and the period at which each key node sends a high pulse to the feeder.
+fn key_node_periods<'a>(
+    module_map: &ModuleMap<'a>,
+    feeder: &'a str,
+    key_nodes: &Vec<&'a str>,
+) -> Result<Vec<(&'a str, i64, i64)>, String> {
+    let mut circuit = Circuit::new(module_map);
+    for key_node in key_nodes {
+        circuit.subscribe(key_node, feeder);
+    }
+    // The presses at which each key node has sent a high pulse to the feeder, up to two.
+    let mut firing_presses: HashMap<&str, Vec<i64>> = HashMap::new();
+    while firing_presses.values().filter(|p| p.len() >= 2).count() < key_nodes.len() {
+        if circuit.num_presses >= MAX_ANALYSIS_PRESSES {
+            let missing: Vec<&str> = key_nodes
+                .iter()
+                .filter(|k| firing_presses.get(*k).map_or(0, |p| p.len()) < 2)
+                .copied()
+                .collect();
+            return Err(format!(
+                "no period found within {MAX_ANALYSIS_PRESSES} presses for inputs of {feeder}: {}",
+                missing.join(", ")
+            ));
+        }
+        circuit.press_button();
+        for (press, pulse) in circuit.take_subscribed_pulses() {
+            let presses = firing_presses.entry(pulse.source).or_default();
+            if !pulse.is_low && presses.len() < 2 && presses.last() != Some(&press) {
+                presses.push(press);
+            }
+        }
+    }
+    return Ok(key_nodes
+        .iter()
+        .map(|k| {
+            let presses = &firing_presses[k];
+            (*k, presses[0], presses[1] - presses[0])
+        })
+        .collect());
+}
+
+// Computes the number of button presses until rx receives a low pulse.
+//
+// The puzzle inputs feed rx from a single conjunction, which sends a low pulse only when all its
+// inputs (the key nodes) have most recently sent high pulses. Each key node is driven by an
+// independent counter sub-network, and sends a high pulse periodically. We find the first
+// press and period of each key node by simulation, and combine them with the Chinese
+// remainder theorem. For the usual inputs, the first press equals the period, and this
+// reduces to the lcm of the periods.
+// Small networks on which this fails are simulated instead.
+fn presses_until_rx_low(module_map: &ModuleMap) -> Result<i64, String> {
+    match analyzed_presses_until_rx_low(module_map) {
+        Ok(presses) => return Ok(presses),
+        Err(e) => {
+            let num_flipflops = module_map.values().filter(|m| m.0 == Some('%')).count();
+            if num_flipflops <= MAX_BRUTE_FORCE_FLIPFLOPS {
+                return brute_force_presses_until_rx_low(module_map);
+            }
+            return Err(e);
+        }
+    }
+}
+
+// The analysis of presses_until_rx_low, which fails unless the network has its structure.
+fn analyzed_presses_until_rx_low(module_map: &ModuleMap) -> Result<i64, String> {
+    let (feeder, key_nodes) = find_key_nodes(module_map)?;
+    let periods = key_node_periods(module_map, feeder, &key_nodes)?;
+
+    let mut combined: (i64, i64) = (0, 1);
+    for (key_node, first, period) in &periods {
+        combined = match crt(combined, (first % period, *period)) {
+            Some(c) => c,
+            None => {
+                return Err(format!(
+                    "the periods of the inputs of {feeder} never align, at {key_node}"
+                ));
+            }
+        };
+    }
+    // The key nodes only start firing after their first press, so skip ahead to the first
+    // common press not before that.
+    let (mut presses, period) = combined;
+    let latest_first = match periods.iter().map(|(_, first, _)| *first).max() {
+        Some(first) => first,
+        None => {
+            return Err(format!("rx is fed by {feeder}, which has no inputs"));
+        }
+    };
+    if presses < latest_first {
+        presses += (latest_first - presses + period - 1) / period * period;
+    }
+    return Ok(presses);
+}
+
+// Presses the button until rx receives a low pulse, or the circuit is back in a state it was in
+// before, after which it only repeats itself.
+fn brute_force_presses_until_rx_low(module_map: &ModuleMap) -> Result<i64, String> {
+    let mut circuit = Circuit::new(module_map);
+    for (name, (_, destinations)) in module_map {
+        if destinations.contains(&"rx") {
+            circuit.subscribe(name, "rx");
+        }
+    }
+    // The press after which the circuit was in each state.
+    let mut visited: HashMap<Vec<bool>, i64> = HashMap::from([(circuit.state.bits(), 0)]);
+    loop {
+        circuit.press_button();
+        let subscribed_pulses = circuit.take_subscribed_pulses();
+        if subscribed_pulses.iter().any(|(_, pulse)| pulse.is_low) {
+            return Ok(circuit.num_presses);
+        }
+        if let Some(previous) = visited.insert(circuit.state.bits(), circuit.num_presses) {
+            return Err(format!(
+                "rx never receives a low pulse, the circuit repeats the state after press \
+                 {previous} at press {}",
+                circuit.num_presses
+            ));
+        }
+    }
+}
+
+fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        return (a, 1, 0);
+    }
+    let (g, x, y) = extended_gcd(b, a % b);
+    return (g, y, x - (a / b) * y);
+}
+
+// Combines the congruences n = r1 (mod m1) and n = r2 (mod m2) into n = r (mod lcm(m1, m2)),
+// or None if they are incompatible. The moduli need not be relatively prime.
+fn crt((r1, m1): (i64, i64), (r2, m2): (i64, i64)) -> Option<(i64, i64)> {
+    let (g, p, _) = extended_gcd(m1 as i128, m2 as i128);
+    let diff = (r2 - r1) as i128;
+    if diff % g != 0 {
+        return None;
+    }
+    let lcm = m1 as i128 / g * m2 as i128;
+    let k = (diff / g * p).rem_euclid(m2 as i128 / g);
+    let r = (r1 as i128 + k * m1 as i128).rem_euclid(lcm);
+    return Some((r as i64, lcm as i64));
 }
 
 // Returns map from module name to module type and list of destination module names.
-fn parse_modules(input: &str) -> HashMap<&str, (Option<char>, Vec<&str>)> {
+fn parse_modules(input: &str) -> ModuleMap<'_> {
     // Parse the data into a reasonable data type.
     let module_data: Vec<(Option<char>, &str, Vec<&str>)> = all_consuming(separated_list1(
         line_ending,
@@ -1,9 +1,10 @@
+use crate::day_9::PolynomialSequence;
 use crate::geometry::Point;
 
-use std::cmp;
-use std::collections::HashMap;
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+
 use std::collections::HashSet;
-use std::collections::VecDeque;
 
 const DIR_LEFT: Point = Point { x: -1, y: 0 };
 const DIR_RIGHT: Point = Point { x: 1, y: 0 };
@@ -27,17 +28,7 @@ pub fn solve_part_1(text: &String) -> () {
     let height = map.len() as i64;
     let width = map[0].len() as i64;
     let is_within_bounds = |p: Point| p.x >= 0 && p.x < width && p.y >= 0 && p.y < height;
-    let mut start = Point { x: 0, y: 0 };
-    for (y, row) in map.iter().enumerate() {
-        for (x, c) in row.iter().enumerate() {
-            if *c == 'S' {
-                start = Point {
-                    x: x as i64,
-                    y: y as i64,
-                };
-            }
-        }
-    }
+    let start = find_start(&map);
     let mut positions: HashSet<Point> = HashSet::from([start]);
     for _ in 0..64 {
         let mut new_positions = HashSet::new();
@@ -58,171 +49,210 @@ pub fn solve_part_1(text: &String) -> () {
     println!("Expected puzzle answer:      3782");
 }
 
-fn count_reachable_with_steps(
-    map: &Vec<Vec<char>>,
-    start: Point,
-    num_steps: i64,
-    count_even: bool,
-) -> i64 {
+// Finds the single 'S' on the map.
+fn find_start(map: &Vec<Vec<char>>) -> Point {
+    for (y, row) in map.iter().enumerate() {
+        for (x, c) in row.iter().enumerate() {
+            if *c == 'S' {
+                return Point {
+                    x: x as i64,
+                    y: y as i64,
+                };
+            }
+        }
+    }
+    panic!("no starting point 'S' on the map");
+}
+
+// Breadth-first search over the infinitely tiled map, up to |max_steps| steps from |start|.
+// Returns the number of plots reachable in exactly n steps, for each n in 0..=max_steps.
+// A plot at distance d is reachable in exactly n >= d steps iff n - d is even, since
+// one can always step back and forth.
+fn count_reachable_infinite_bfs(map: &Vec<Vec<char>>, start: Point, max_steps: i64) -> Vec<i64> {
     let height = map.len() as i64;
     let width = map[0].len() as i64;
-    let is_within_bounds = |p: Point| p.x >= 0 && p.x < width && p.y >= 0 && p.y < height;
-    let mut visited: HashSet<Point> = HashSet::new();
-    let mut even_visited: HashSet<Point> = HashSet::new();
-    let mut positions: HashSet<Point> = HashSet::from([start]);
-    let mut steps = 0;
-    while positions.len() > 0 && steps <= num_steps {
-        let mut new_positions = HashSet::new();
-        for p in positions {
-            visited.insert(p);
-            if steps % 2 == 0 {
-                even_visited.insert(p);
-            }
+    let is_garden =
+        |p: Point| map[p.y.rem_euclid(height) as usize][p.x.rem_euclid(width) as usize] != '#';
+    let mut visited: HashSet<Point> = HashSet::from([start]);
+    let mut frontier: Vec<Point> = vec![start];
+    // Number of plots at each exact distance from the start.
+    let mut num_at_distance: Vec<i64> = Vec::new();
+    for _ in 0..=max_steps {
+        num_at_distance.push(frontier.len() as i64);
+        let mut new_frontier = Vec::new();
+        for p in frontier {
             for d in [DIR_LEFT, DIR_UP, DIR_RIGHT, DIR_DOWN] {
                 let np = p + d;
-                if !visited.contains(&np)
-                    && is_within_bounds(np)
-                    && map[np.y as usize][np.x as usize] != '#'
-                {
-                    new_positions.insert(np);
+                if is_garden(np) && visited.insert(np) {
+                    new_frontier.push(np);
                 }
             }
         }
-        positions = new_positions;
-        steps += 1;
+        frontier = new_frontier;
     }
 
-    if count_even {
-        return even_visited.len() as i64;
-    } else {
-        return (visited.len() - even_visited.len()) as i64;
+    let mut num_reachable: Vec<i64> = Vec::new();
+    for n in 0..num_at_distance.len() {
+        let previous = if n >= 2 { num_reachable[n - 2] } else { 0 };
+        num_reachable.push(previous + num_at_distance[n]);
     }
+    return num_reachable;
 }
 
-pub fn solve_part_2(text: &String) -> () {
-    // This solution is a mess. Can be significantly cleaned up and improved wrt performance.
-    // Basically:
-    // - The input has a very special format: (frustratingly _not_ all shared with the example input)
-    //   - The starting point is right in the center
-    //   - The map is square, with odd side length
-    //   - There is a free path from the center to the boundary, and all along the boundary
-    //   - There is no "unexpectedly long" path created by #-characters to reach any point in the map from any point on the boundary
-    // - All copies of the map that are reachable within somewhat less than 26501365 steps can be completely explored
-    //   - The total step count of these copies is just the number of copies times the number of reachable endpoints within
-    // - All remaining copies are along the boundary of the "rhombus" at 26501365 steps from the starting point
-    //   - The copies are only entered from a few boundary points and with a certain number of remaining steps
-    //     - We can again just multiply the number of each configuration times the number of reachable endpoints within
-    //     - ...or in this code's less thought-through implementation, use a cache to avoid recomputing the endpoint count
+fn gcd(a: i64, b: i64) -> i64 {
+    match b {
+        0 => a,
+        _ => gcd(b, a % b),
+    }
+}
 
-    let map = parse_map(text);
+// Step counts up to this are cheap enough for BFS, which also bounds the samples for the fit.
+const MAX_BFS_STEPS: i64 = 3000;
+
+// Counts the plots reachable in exactly |num_steps| steps on the infinitely tiled map.
+//
+// Step counts up to |max_bfs_steps| are answered by plain BFS. For larger step counts, the
+// count is sampled at n_k = r + k * period, with r = num_steps % period and period a multiple
+// of both map dimensions. Once the reachable region has outgrown the map's irregularities,
+// f(n_k) is quadratic in k, and we extrapolate from the samples where it is.
+// The fit is only trusted if it also predicts all the samples following the fitted ones, which
+// compares the extrapolation against brute force on the largest step counts we can afford.
+// If a few samples do not settle, as many as |max_bfs_steps| allows are tried. Maps whose
+// width and height share no factor have a long period, and may not be sampled enough.
+fn count_reachable_infinite(
+    map: &Vec<Vec<char>>,
+    start: Point,
+    num_steps: i64,
+    max_bfs_steps: i64,
+) -> Result<i64, String> {
+    const NUM_SAMPLES: i64 = 10;
+    // Number of samples beyond the fitted window that the fit must predict.
+    const NUM_VERIFIED: usize = 2;
+
+    if num_steps <= max_bfs_steps {
+        return Ok(count_reachable_infinite_bfs(map, start, num_steps)[num_steps as usize]);
+    }
     let height = map.len() as i64;
     let width = map[0].len() as i64;
-    assert!(height == width);
-
-    let mut start = Point { x: 0, y: 0 };
-    for (y, row) in map.iter().enumerate() {
-        for (x, c) in row.iter().enumerate() {
-            if *c == 'S' {
-                start = Point {
-                    x: x as i64,
-                    y: y as i64,
-                };
+    let lcm = width / gcd(width, height) * height;
+    // Moving one period shifts the parity of the tile grid unless the period is even, so
+    // try the doubled period if the single one does not settle into a quadratic.
+    for period in [lcm, 2 * lcm] {
+        let max_num_samples = (max_bfs_steps - num_steps % period) / period + 1;
+        let mut sample_counts = vec![NUM_SAMPLES.min(max_num_samples), max_num_samples];
+        sample_counts.dedup();
+        for num_samples in sample_counts {
+            if num_samples < 3 + NUM_VERIFIED as i64 {
+                continue;
+            }
+            let max_sampled_steps = num_steps % period + (num_samples - 1) * period;
+            let num_reachable = count_reachable_infinite_bfs(map, start, max_sampled_steps);
+            let samples: Vec<BigInt> = (0..num_samples)
+                .map(|k| BigInt::from(num_reachable[(num_steps % period + k * period) as usize]))
+                .collect();
+            // Fit a quadratic to the first 3 samples from which on all of them are quadratic,
+            // confirmed by at least NUM_VERIFIED more.
+            for i in 0..samples.len() - 2 - NUM_VERIFIED {
+                let num_confirmations = samples.len() - i - 3;
+                if let Ok(sequence) = PolynomialSequence::fit(&samples[i..], num_confirmations) {
+                    let k = BigInt::from(num_steps / period - i as i64);
+                    return sequence.at(&k).to_i64().ok_or(format!(
+                        "reachable plot count after {num_steps} steps overflows"
+                    ));
+                }
             }
         }
     }
+    return Err(format!(
+        "reachable plot count after {num_steps} steps does not settle into quadratic growth \
+         within {max_bfs_steps} steps, with a map period of {lcm}"
+    ));
+}
 
-    assert!(start.x % 2 == 1);
-    assert!(start.y % 2 == 1);
-    let even_visited =
-        count_reachable_with_steps(&map, start, width * width, /*count_even*/ true);
-    let odd_visited =
-        count_reachable_with_steps(&map, start, width * width, /*count_even*/ false);
+pub fn solve_part_2(text: &String) -> () {
+    // The number of reachable plots in the tiled garden grows quadratically once the
+    // reachable region is large compared to the map, see count_reachable_infinite.
+    // This needs the growth to settle within the step counts that BFS can sample, which
+    // holds for the puzzle's square map.
+    let map = parse_map(text);
+    let start = find_start(&map);
 
     let num_steps = 26501365;
-    // We can reach and fully flood any map tile index (nx,ny) where:
-    // width * (|nx| + |ny| + 1) - 1 <= num_steps
-    let nx_ny_sum: i64 = cmp::max(0, (num_steps + 1) / width - 1);
-    let num_fully_visited_tiles = (nx_ny_sum + 1)
-        * (nx_ny_sum + 1)
-        * (match (num_steps + nx_ny_sum) % 2 {
-            0 => even_visited,
-            _ => odd_visited,
-        })
-        + nx_ny_sum
-            * nx_ny_sum
-            * (match (num_steps + nx_ny_sum) % 2 {
-                1 => even_visited,
-                _ => odd_visited,
-            });
-
-    // What remains is the contribution from not-necessarily-fully traversed tiles.
-    // These tiles have a sum |nx|+|ny| greater than nx_ny_sum.
-    // We start from those, and flood-fill our way from there.
-    // Cache pairs of entry points and remaining steps to save compute.
-
-    let mut nxny_visited: HashSet<Point> = HashSet::new();
-    let scalar_sign = |a: i64| match a {
-        0 => 0,
-        _ => a / a.abs(),
-    };
-    let sign = |p: &Point| Point {
-        x: scalar_sign(p.x),
-        y: scalar_sign(p.y),
-    };
-    let multiply_vector = |d: Point, n: i64| Point {
-        x: d.x * n,
-        y: d.y * n,
-    };
-    let used_steps_1d = |a: i64| match a {
-        0 => 0,
-        _ => (width + 1) / 2 + (a.abs() - 1) * width,
-    };
-    let remaining_steps_from =
-        |nn: Point, n_steps: i64| n_steps - used_steps_1d(nn.x) - used_steps_1d(nn.y);
-    // Cache: Map starting point within tile + number of steps within tile to the number of tiles visitable.
-    let mut tile_flood_cache: HashMap<(Point, i64), i64> = HashMap::new();
-    let mut to_visit: VecDeque<Point> = VecDeque::new();
-    for n2 in 0..nx_ny_sum + 1 {
-        let n1 = nx_ny_sum + 1 - n2;
-        to_visit.push_back(Point { x: n1, y: n2 });
-        to_visit.push_back(Point { x: -n2, y: n1 });
-        to_visit.push_back(Point { x: -n1, y: -n2 });
-        to_visit.push_back(Point { x: n2, y: -n1 });
-    }
-    let mut num_partial_visited = 0;
-    while let Some(nn) = to_visit.pop_front() {
-        if nn.x.abs() + nn.y.abs() <= nx_ny_sum || nxny_visited.contains(&nn) {
-            // Already visited.
-            continue;
+    let num_visited = match count_reachable_infinite(&map, start, num_steps, MAX_BFS_STEPS) {
+        Ok(num) => num,
+        Err(e) => {
+            panic!("unable to extrapolate: {}", e);
         }
-        nxny_visited.insert(nn);
-        // Given nx,ny, compute closest entry point:
-        let nn_start = start - multiply_vector(sign(&nn), (width - 1) / 2);
-        // Compute number of remaining steps:
-        let remaining_steps = remaining_steps_from(nn, num_steps);
-        let count_even = remaining_steps % 2 == 0;
-        let num_new_visited: i64;
-        match tile_flood_cache.get(&(nn_start, remaining_steps)) {
-            Some(num) => {
-                num_new_visited = *num;
-            }
-            None => {
-                let num = count_reachable_with_steps(&map, nn_start, remaining_steps, count_even);
-                tile_flood_cache.insert((nn_start, remaining_steps), num);
-                num_new_visited = num;
-            }
-        };
-        num_partial_visited += num_new_visited;
-        for dir in [DIR_LEFT, DIR_RIGHT, DIR_UP, DIR_DOWN] {
-            let nnn = nn + dir;
-            if remaining_steps_from(nnn, num_steps) >= 0 {
-                to_visit.push_back(nnn);
-            }
+    };
+
+    println!("Tiles reachable after many steps: {num_visited}");
+    println!("Expected puzzle answer:           630661863455116");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The extrapolation, sampling at most |max_bfs_steps| steps, must agree with BFS on all
+    // step counts in |steps|.
+    fn check_against_bfs(text: &str, max_bfs_steps: i64, steps: std::ops::Range<i64>) {
+        let map = parse_map(text);
+        let start = find_start(&map);
+        let num_reachable = count_reachable_infinite_bfs(&map, start, steps.end);
+        for num_steps in steps {
+            assert_eq!(
+                count_reachable_infinite(&map, start, num_steps, max_bfs_steps),
+                Ok(num_reachable[num_steps as usize]),
+                "after {num_steps} steps"
+            );
         }
     }
 
-    let num_visited = num_fully_visited_tiles + num_partial_visited;
+    #[test]
+    fn square_map_matches_bfs() {
+        let text = "...........\n\
+                    .....###.#.\n\
+                    .###.##..#.\n\
+                    ..#.#...#..\n\
+                    ....#.#....\n\
+                    .##..S####.\n\
+                    .##..#...#.\n\
+                    .......##..\n\
+                    .##.#.####.\n\
+                    .##..##.##.\n\
+                    ...........";
+        check_against_bfs(text, 200, 300..306);
+    }
 
-    println!("Tiles reachable after many steps: {num_visited}");
-    println!("Expected puzzle answer:           630661863455116");
+    #[test]
+    fn rectangular_map_matches_bfs() {
+        let text = ".............\n\
+                    ......#......\n\
+                    .....S.......\n\
+                    ..........#..";
+        check_against_bfs(text, 300, 400..404);
+    }
+
+    // The growth on this map does not settle within the samples, so step counts that BFS can
+    // afford must be answered by BFS, and larger ones must not be extrapolated.
+    #[test]
+    fn unsettled_map_falls_back_to_bfs() {
+        let text = "..#....#.....\n\
+                    .....#...#.#.\n\
+                    .#...S..#....\n\
+                    ....#.....#..";
+        check_against_bfs(text, MAX_BFS_STEPS, 500..502);
+        let map = parse_map(text);
+        let start = find_start(&map);
+        assert!(count_reachable_infinite(&map, start, 400, 300).is_err());
+    }
+
+    #[test]
+    fn off_center_start_matches_bfs() {
+        let text = "S.....\n\
+                    ..#...\n\
+                    ....#.\n\
+                    .#....";
+        check_against_bfs(text, 200, 300..304);
+    }
 }
@@ -1,6 +1,6 @@
 use nom::{
     bytes::complete::tag,
-    character::complete::{line_ending, u64},
+    character::complete::{i64, line_ending},
     combinator::all_consuming,
     error::Error,
     multi::separated_list1,
@@ -9,53 +9,78 @@ use nom::{
 
 use std::cmp;
 
-use std::collections::HashSet;
+use std::collections::HashMap;
 
-type Point3 = (usize, usize, usize);
+type Point3 = (i64, i64, i64);
 
-fn parse_and_drop_bricks(text: &String) -> (Vec<usize>, Vec<Vec<usize>>, Vec<Vec<usize>>) {
-    let bricks = parse_bricks(text);
+// A brick as its lowest and highest corner.
+type Brick = (Point3, Point3);
+
+// The bricks after falling into place, indexed like the input bricks, and how they rest on
+// each other.
+struct SettledStack {
+    bricks: Vec<Brick>,
+    bricks_by_z_asc: Vec<usize>,
+    bricks_below: Vec<Vec<usize>>,
+    bricks_on_top: Vec<Vec<usize>>,
+}
+
+// Lets the bricks fall until they rest on the ground (z = 0) or on other bricks.
+// The height map is sparse, so any footprint works, including negative coordinates.
+fn settle_bricks(bricks: &Vec<Brick>) -> SettledStack {
+    let mut settled: Vec<Brick> = bricks.clone();
     let mut bricks_below: Vec<Vec<usize>> = vec![Vec::new(); bricks.len()];
     let mut bricks_on_top: Vec<Vec<usize>> = vec![Vec::new(); bricks.len()];
     let mut bricks_by_z_asc: Vec<usize> = (0..bricks.len()).collect();
-    bricks_by_z_asc.sort_unstable_by_key(|i| cmp::min(bricks[*i].0 .2, bricks[*i].1 .2));
+    bricks_by_z_asc.sort_unstable_by_key(|i| bricks[*i].0 .2);
 
-    let mut zbuf: [[usize; 10]; 10] = [[0; 10]; 10];
-    let mut zbuf_idx: [[Option<usize>; 10]; 10] = [[None; 10]; 10];
+    // The top z and index of the highest brick at each (x, y) covered so far.
+    let mut zbuf: HashMap<(i64, i64), (i64, usize)> = HashMap::new();
 
     for i in &bricks_by_z_asc {
-        let brick = bricks[*i];
-        let brick_z_height =
-            cmp::max(brick.0 .2, brick.1 .2) - cmp::min(brick.0 .2, brick.1 .2) + 1;
-        let mut max_z_below = 0;
-        for x in brick.0 .0..brick.1 .0 + 1 {
-            for y in brick.0 .1..brick.1 .1 + 1 {
-                max_z_below = cmp::max(max_z_below, zbuf[y][x]);
-            }
-        }
-        let mut supports: Vec<usize> = Vec::new();
-        for x in brick.0 .0..brick.1 .0 + 1 {
-            for y in brick.0 .1..brick.1 .1 + 1 {
-                if max_z_below > 0 && zbuf[y][x] == max_z_below {
-                    let support_index = zbuf_idx[y][x].unwrap();
-                    if supports.last() != Some(&support_index) {
-                        supports.push(support_index);
-                    }
-                }
-                zbuf[y][x] = max_z_below + brick_z_height;
-                zbuf_idx[y][x] = Some(*i);
-            }
+        let ((x0, y0, z0), (x1, y1, z1)) = bricks[*i];
+        let footprint = || (x0..x1 + 1).flat_map(|x| (y0..y1 + 1).map(move |y| (x, y)));
+        let max_z_below = footprint()
+            .filter_map(|xy| zbuf.get(&xy))
+            .map(|(z, _)| *z)
+            .max()
+            .unwrap_or(0);
+        let mut supports: Vec<usize> = footprint()
+            .filter_map(|xy| zbuf.get(&xy))
+            .filter(|(z, _)| *z == max_z_below)
+            .map(|(_, j)| *j)
+            .collect();
+        supports.sort_unstable();
+        supports.dedup();
+        let new_z0 = max_z_below + 1;
+        let new_z1 = new_z0 + z1 - z0;
+        for xy in footprint() {
+            zbuf.insert(xy, (new_z1, *i));
         }
+        settled[*i] = ((x0, y0, new_z0), (x1, y1, new_z1));
         for j in &supports {
             bricks_on_top[*j].push(*i);
         }
         bricks_below[*i] = supports;
     }
-    return (bricks_by_z_asc, bricks_below, bricks_on_top);
+    return SettledStack {
+        bricks: settled,
+        bricks_by_z_asc,
+        bricks_below,
+        bricks_on_top,
+    };
+}
+
+fn parse_and_drop_bricks(text: &str) -> SettledStack {
+    return settle_bricks(&parse_bricks(text));
 }
 
 pub fn solve_part_1(text: &String) -> () {
-    let (_, bricks_below, bricks_on_top) = parse_and_drop_bricks(text);
+    let SettledStack {
+        bricks_below,
+        bricks_on_top,
+        ..
+    } = parse_and_drop_bricks(text);
 
     let answer = (0..bricks_on_top.len())
         .filter(|i| bricks_on_top[*i].iter().all(|j| bricks_below[*j].len() > 1))
@@ -65,42 +90,166 @@ pub fn solve_part_1(text: &String) -> () {
     println!("Expected puzzle answer: 413");
 }
 
-pub fn solve_part_2(text: &String) -> () {
-    let (bricks_by_z_asc, bricks_below, _) = parse_and_drop_bricks(text);
-
-    let mut support_closure: Vec<HashSet<usize>> = vec![HashSet::new(); bricks_by_z_asc.len()];
-    let mut sum_num_fallen_bricks = 0;
-    for i in bricks_by_z_asc.iter().rev() {
-        let sc = support_closure[*i].clone();
-        let mut fallen: HashSet<usize> = HashSet::from([*i]);
-        // Iterating from below over the bricks above i, collect all the bricks that would fall by removing i.
-        for j in bricks_by_z_asc.iter().filter(|j| sc.contains(j)) {
-            // A brick falls if all the bricks it rests on fall.
-            if bricks_below[*j].iter().all(|k| fallen.contains(k)) {
-                fallen.insert(*j);
+// The dominator tree of the support graph, which has an edge from each brick to each brick
+// resting on it, and from the ground to each brick resting on the ground.
+// Brick j dominates brick i if every chain of supports from the ground to i passes through j,
+// i.e., if j is disintegrated, i falls. So the bricks falling when j is disintegrated are
+// exactly j's descendants in the dominator tree.
+struct DominatorTree {
+    // Immediate dominator of each brick, or None if it is the ground.
+    idom: Vec<Option<usize>>,
+    children: Vec<Vec<usize>>,
+    // Number of bricks in the subtree rooted at each brick, including itself.
+    subtree_size: Vec<usize>,
+}
+
+impl DominatorTree {
+    // Lengauer-Tarjan handles general graphs, but the support graph is a DAG, which allows for
+    // something simpler: visiting bricks in topological order (by z), the immediate dominator of
+    // a brick is the lowest common ancestor in the tree of the bricks it rests on.
+    // With binary lifting for the lowest common ancestors, this runs in O(E log V).
+    fn new(stack: &SettledStack) -> DominatorTree {
+        let num_bricks = stack.bricks.len();
+        let num_levels = (usize::BITS - num_bricks.leading_zeros()) as usize + 1;
+        // Ancestors at distance 2^k, per level k. The ground is None.
+        let mut ancestors: Vec<Vec<Option<usize>>> = vec![vec![None; num_bricks]; num_levels];
+        let mut depth: Vec<usize> = vec![0; num_bricks];
+        let ancestor_at_depth = |ancestors: &Vec<Vec<Option<usize>>>,
+                                 mut brick: Option<usize>,
+                                 steps: usize|
+         -> Option<usize> {
+            for (k, level) in ancestors.iter().enumerate() {
+                if let Some(b) = brick {
+                    if steps & (1 << k) != 0 {
+                        brick = level[b];
+                    }
+                }
+            }
+            return brick;
+        };
+        let lowest_common_ancestor = |ancestors: &Vec<Vec<Option<usize>>>,
+                                      depth: &Vec<usize>,
+                                      a: Option<usize>,
+                                      b: Option<usize>|
+         -> Option<usize> {
+            let depth_of = |x: Option<usize>| x.map_or(0, |x| depth[x]);
+            let (mut a, mut b) = match depth_of(a) >= depth_of(b) {
+                true => (
+                    ancestor_at_depth(ancestors, a, depth_of(a) - depth_of(b)),
+                    b,
+                ),
+                false => (
+                    a,
+                    ancestor_at_depth(ancestors, b, depth_of(b) - depth_of(a)),
+                ),
+            };
+            if a == b {
+                return a;
+            }
+            for level in ancestors.iter().rev() {
+                let (na, nb) = (level[a.unwrap()], level[b.unwrap()]);
+                if na != nb {
+                    (a, b) = (na, nb);
+                }
+            }
+            return ancestors[0][a.unwrap()];
+        };
+
+        let mut idom: Vec<Option<usize>> = vec![None; num_bricks];
+        for i in &stack.bricks_by_z_asc {
+            let mut below = stack.bricks_below[*i].iter().map(|j| Some(*j));
+            let first = below.next().unwrap_or(None);
+            let dominator = below.fold(first, |lca, j| {
+                lowest_common_ancestor(&ancestors, &depth, lca, j)
+            });
+            idom[*i] = dominator;
+            depth[*i] = dominator.map_or(0, |d| depth[d]) + 1;
+            ancestors[0][*i] = dominator;
+            for k in 1..num_levels {
+                ancestors[k][*i] = ancestors[k - 1][*i].and_then(|a| ancestors[k - 1][a]);
             }
         }
-        sum_num_fallen_bricks += fallen.len() - 1;
 
-        for j in &bricks_below[*i] {
-            support_closure[*j].insert(*i);
-            for k in &sc {
-                support_closure[*j].insert(*k);
+        let mut children: Vec<Vec<usize>> = vec![Vec::new(); num_bricks];
+        let mut subtree_size: Vec<usize> = vec![1; num_bricks];
+        for i in stack.bricks_by_z_asc.iter().rev() {
+            if let Some(d) = idom[*i] {
+                children[d].push(*i);
+                subtree_size[d] += subtree_size[*i];
             }
         }
+        return DominatorTree {
+            idom,
+            children,
+            subtree_size,
+        };
     }
 
+    // Returns the bricks that fall when |brick| is disintegrated.
+    fn falling_bricks(&self, brick: usize) -> Vec<usize> {
+        let mut fallen: Vec<usize> = Vec::new();
+        let mut to_visit: Vec<usize> = self.children[brick].clone();
+        while let Some(i) = to_visit.pop() {
+            fallen.push(i);
+            to_visit.extend(&self.children[i]);
+        }
+        fallen.sort_unstable();
+        return fallen;
+    }
+}
+
+pub fn solve_part_2(text: &String) -> () {
+    let stack = parse_and_drop_bricks(text);
+    let dominators = DominatorTree::new(&stack);
+    let sum_num_fallen_bricks: usize = dominators.subtree_size.iter().map(|n| n - 1).sum();
+
     println!("Sum of number of bricks that can fall: {sum_num_fallen_bricks}");
     println!("Expected puzzle answer:                41610");
 }
 
-fn parse_bricks(input: &str) -> Vec<(Point3, Point3)> {
+// Prints, for each brick by input line, the bricks that fall when it is disintegrated, and the
+// brick it is closest to fall with, i.e. its immediate dominator.
+pub fn print_chain_reactions(text: &String) -> () {
+    let stack = parse_and_drop_bricks(text);
+    let dominators = DominatorTree::new(&stack);
+    for i in 0..stack.bricks.len() {
+        let falls_with = match dominators.idom[i] {
+            Some(d) => format!("brick {}", d + 1),
+            None => "nothing".to_string(),
+        };
+        let fallen: Vec<String> = dominators
+            .falling_bricks(i)
+            .iter()
+            .map(|j| (j + 1).to_string())
+            .collect();
+        println!(
+            "Brick {}: falls with {falls_with}, topples {} bricks: {}",
+            i + 1,
+            fallen.len(),
+            fallen.join(" ")
+        );
+    }
+}
+
+// Prints the settled bricks in the input format, from the bottom up.
+pub fn print_settled_bricks(text: &String) -> () {
+    let stack = parse_and_drop_bricks(text);
+    for i in &stack.bricks_by_z_asc {
+        let ((x0, y0, z0), (x1, y1, z1)) = stack.bricks[*i];
+        println!("{x0},{y0},{z0}~{x1},{y1},{z1}");
+    }
+    let height = stack.bricks.iter().map(|b| b.1 .2).max().unwrap_or(0);
+    println!("Height of the settled stack: {height}");
+}
+
+// Parses bricks "x,y,z~x,y,z", normalized so that the first corner is the lowest.
+fn parse_bricks(input: &str) -> Vec<Brick> {
     return all_consuming(separated_list1(
         line_ending::<_, Error<_>>,
         separated_pair(
-            tuple((u64, preceded(tag(","), u64), preceded(tag(","), u64))),
+            tuple((i64, preceded(tag(","), i64), preceded(tag(","), i64))),
             tag("~"),
-            tuple((u64, preceded(tag(","), u64), preceded(tag(","), u64))),
+            tuple((i64, preceded(tag(","), i64), preceded(tag(","), i64))),
         ),
     ))(input)
     .unwrap()
@@ -108,8 +257,8 @@ fn parse_bricks(input: &str) -> Vec<(Point3, Point3)> {
     .iter()
     .map(|(p, q)| {
         (
-            (p.0 as usize, p.1 as usize, p.2 as usize),
-            (q.0 as usize, q.1 as usize, q.2 as usize),
+            (cmp::min(p.0, q.0), cmp::min(p.1, q.1), cmp::min(p.2, q.2)),
+            (cmp::max(p.0, q.0), cmp::max(p.1, q.1), cmp::max(p.2, q.2)),
         )
     })
     .collect();
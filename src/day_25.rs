@@ -7,134 +7,323 @@ use nom::{
     sequence::separated_pair,
 };
 
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
+use std::collections::BinaryHeap;
 use std::collections::HashMap;
-use std::collections::HashSet;
-
-// Karger's Algorithm.
-// https://en.wikipedia.org/wiki/Karger%27s_algorithm
-// Returns a tuple of the edge cut size and the size of each resulting partition.
-// Has about 0.5% chance of finding the true minimum cut on my problem input.
-fn try_min_cut(
-    neighbors_orig: Vec<Vec<usize>>,
-    edges_orig: HashSet<(usize, usize)>,
-) -> (usize, usize, usize) {
-    let mut neighbors = neighbors_orig.clone();
-    let mut edges = edges_orig.iter().cloned().collect::<Vec<(usize, usize)>>();
-    let mut contraction_size: Vec<usize> = Vec::from_iter((0..neighbors.len()).map(|_| 1));
-    // Keep track of which vertices are contracted into which.
-    // If edge (i,j) is contracted into i, then repr[j] = i.
-    let mut repr: Vec<usize> = Vec::from_iter(0..neighbors.len());
-
-    let mut num_removed_last = 0;
-    let mut last_i = 0;
-    let mut last_j = 0;
-    let mut rng = rand::thread_rng();
-    while edges.len() > 0 {
-        let random_index = rng.gen_range(0..edges.len());
-        let (mut i, mut j) = edges.swap_remove(random_index);
-        while repr[i] != i {
-            i = repr[i];
-        }
-        while repr[j] != j {
-            j = repr[j];
+use std::fs;
+
+// The wiring diagram as an undirected graph, with components numbered in order of appearance.
+struct Graph<'a> {
+    names: Vec<&'a str>,
+    edges: Vec<(usize, usize)>,
+}
+
+fn build_graph<'a>(connections: Vec<(&'a str, Vec<&'a str>)>) -> Graph<'a> {
+    let mut names: Vec<&str> = Vec::new();
+    let mut ids: HashMap<&str, usize> = HashMap::new();
+    let mut edges: Vec<(usize, usize)> = Vec::new();
+    let mut get_or_create_id = |s: &'a str| -> usize {
+        return *ids.entry(s).or_insert_with(|| {
+            names.push(s);
+            names.len() - 1
+        });
+    };
+    for (name, adj) in connections {
+        let i = get_or_create_id(name);
+        for nbr_name in adj {
+            let j = get_or_create_id(nbr_name);
+            edges.push((i, j));
         }
-        if i == j {
-            // Edge has already been contracted into a self-loop, ignore.
-            continue;
+    }
+    return Graph { names, edges };
+}
+
+// A partition of the graph vertices in two, and the edges crossing between them.
+struct Cut {
+    edges: Vec<(usize, usize)>,
+    side_a: Vec<usize>,
+    side_b: Vec<usize>,
+}
+
+impl Cut {
+    // Completes a cut from the membership of each vertex in side A.
+    fn from_sides(graph: &Graph, in_a: &Vec<bool>) -> Cut {
+        return Cut {
+            edges: graph
+                .edges
+                .iter()
+                .filter(|(i, j)| in_a[*i] != in_a[*j])
+                .copied()
+                .collect(),
+            side_a: (0..in_a.len()).filter(|i| in_a[*i]).collect(),
+            side_b: (0..in_a.len()).filter(|i| !in_a[*i]).collect(),
+        };
+    }
+}
+
+// Stoer-Wagner Algorithm.
+// https://en.wikipedia.org/wiki/Stoer%E2%80%93Wagner_algorithm
+// Deterministically finds a global minimum cut in O(VE log V) time, using a heap for the
+// maximum adjacency ordering of each phase.
+fn stoer_wagner_min_cut(graph: &Graph) -> Cut {
+    let num_vertices = graph.names.len();
+    assert!(
+        num_vertices >= 2,
+        "cannot cut a graph with less than two vertices"
+    );
+    // Edge weights between merged vertices. Parallel edges arise from merging.
+    let mut adjacency: Vec<HashMap<usize, i64>> = vec![HashMap::new(); num_vertices];
+    for (i, j) in &graph.edges {
+        if i != j {
+            *adjacency[*i].entry(*j).or_insert(0) += 1;
+            *adjacency[*j].entry(*i).or_insert(0) += 1;
         }
-        // Contract this edge, merging j into i.
-        let nbrs_j = neighbors[j].clone();
-        // Update i and all j-neighbors to point to each other.
-        for k in &nbrs_j {
-            for l in neighbors[*k].iter_mut() {
-                if *l == j {
-                    *l = j;
+    }
+    // The original vertices merged into each remaining vertex.
+    let mut members: Vec<Vec<usize>> = (0..num_vertices).map(|i| vec![i]).collect();
+    let mut active: Vec<usize> = (0..num_vertices).collect();
+
+    let mut best_weight = i64::MAX;
+    let mut best_side: Vec<usize> = Vec::new();
+    while active.len() > 1 {
+        // Minimum cut phase: add vertices in order of how strongly they connect to those added.
+        let mut connectivity: Vec<i64> = vec![0; num_vertices];
+        let mut is_added: Vec<bool> = vec![false; num_vertices];
+        let mut heap: BinaryHeap<(i64, usize)> = active.iter().map(|v| (0, *v)).collect();
+        let mut order: Vec<usize> = Vec::new();
+        while let Some((weight, v)) = heap.pop() {
+            if is_added[v] || weight != connectivity[v] {
+                // Stale heap entry.
+                continue;
+            }
+            is_added[v] = true;
+            order.push(v);
+            for (u, w) in &adjacency[v] {
+                if !is_added[*u] {
+                    connectivity[*u] += w;
+                    heap.push((connectivity[*u], *u));
                 }
             }
-            neighbors[i].push(*k);
         }
-        // Update all edges to refer to i instead of j, deleting any self-loops that arise.
-        let mut to_remove: Vec<usize> = Vec::new();
-        for (idx, (u, v)) in edges.iter_mut().enumerate() {
-            if *u == j {
-                *u = i;
-            }
-            if *v == j {
-                *v = i;
-            }
-            if *u == *v {
-                to_remove.push(idx);
-            }
+        let t = order[order.len() - 1];
+        let s = order[order.len() - 2];
+        // The cut of the phase separates t from everything else.
+        if connectivity[t] < best_weight {
+            best_weight = connectivity[t];
+            best_side = members[t].clone();
         }
-        to_remove.reverse();
-        for idx in &to_remove {
-            edges.swap_remove(*idx);
+        // Merge t into s.
+        let t_adjacency = std::mem::take(&mut adjacency[t]);
+        for (u, w) in t_adjacency {
+            adjacency[u].remove(&t);
+            if u != s {
+                *adjacency[s].entry(u).or_insert(0) += w;
+                *adjacency[u].entry(s).or_insert(0) += w;
+            }
         }
+        let t_members = std::mem::take(&mut members[t]);
+        members[s].extend(t_members);
+        active.retain(|v| *v != t);
+    }
 
-        repr[j] = i;
-        if edges.len() == 0 {
-            num_removed_last = 1 + to_remove.len();
-            last_i = i;
-            last_j = j;
+    let mut in_a = vec![false; num_vertices];
+    for v in best_side {
+        in_a[v] = true;
+    }
+    return Cut::from_sides(graph, &in_a);
+}
+
+// Randomly contracts edges of the weighted multigraph until |target| vertices remain.
+// Returns the number of remaining vertices (more than |target| if the graph is disconnected),
+// the contracted edges with parallel edges summed up and self-loops dropped, and the new vertex
+// of each old vertex.
+fn contract(
+    num_vertices: usize,
+    edges: &Vec<(usize, usize, usize)>,
+    target: usize,
+    rng: &mut StdRng,
+) -> (usize, Vec<(usize, usize, usize)>, Vec<usize>) {
+    // Contracting random edges one by one is equivalent to contracting them in order of
+    // exponentially distributed arrival times, where an edge of weight w arrives at rate w.
+    let mut order: Vec<(f64, usize, usize)> = edges
+        .iter()
+        .map(|(i, j, w)| (-(1.0 - rng.gen::<f64>()).ln() / *w as f64, *i, *j))
+        .collect();
+    order.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let mut repr: Vec<usize> = (0..num_vertices).collect();
+    fn find(repr: &mut Vec<usize>, mut i: usize) -> usize {
+        while repr[i] != i {
+            repr[i] = repr[repr[i]];
+            i = repr[i];
+        }
+        return i;
+    }
+    let mut num_remaining = num_vertices;
+    for (_, i, j) in order {
+        if num_remaining <= target {
             break;
         }
-        contraction_size[i] += contraction_size[j];
+        let (ri, rj) = (find(&mut repr, i), find(&mut repr, j));
+        if ri != rj {
+            repr[rj] = ri;
+            num_remaining -= 1;
+        }
     }
 
-    return (
-        num_removed_last,
-        contraction_size[last_i],
-        contraction_size[last_j],
-    );
+    let mut root_index: Vec<Option<usize>> = vec![None; num_vertices];
+    let mut mapping: Vec<usize> = Vec::new();
+    let mut num_contracted = 0;
+    for i in 0..num_vertices {
+        let r = find(&mut repr, i);
+        if root_index[r].is_none() {
+            root_index[r] = Some(num_contracted);
+            num_contracted += 1;
+        }
+        mapping.push(root_index[r].unwrap());
+    }
+    // Sum up parallel edges by sorting them next to each other.
+    let mut contracted: Vec<(usize, usize, usize)> = edges
+        .iter()
+        .map(|(i, j, w)| (mapping[*i], mapping[*j], *w))
+        .filter(|(i, j, _)| i != j)
+        .map(|(i, j, w)| (i.min(j), i.max(j), w))
+        .collect();
+    contracted.sort_unstable();
+    let mut new_edges: Vec<(usize, usize, usize)> = Vec::new();
+    for (i, j, w) in contracted {
+        match new_edges.last_mut() {
+            Some((li, lj, lw)) if (*li, *lj) == (i, j) => *lw += w,
+            _ => new_edges.push((i, j, w)),
+        }
+    }
+    return (num_contracted, new_edges, mapping);
 }
 
-pub fn solve_part_1(text: &String) -> () {
-    let connections = parse_rows(text);
-    let mut names: Vec<&str> = Vec::new();
-    let mut ids: HashMap<&str, usize> = HashMap::new();
-    let mut neighbors: Vec<Vec<usize>> = Vec::new();
-    let mut edges: HashSet<(usize, usize)> = HashSet::new();
-
-    fn get_or_create_id<'a>(
-        s: &'a str,
-        is: &mut HashMap<&'a str, usize>,
-        ns: &mut Vec<&'a str>,
-        nbrs: &mut Vec<Vec<usize>>,
-    ) -> usize {
-        if is.contains_key(s) {
-            return *is.get(s).unwrap();
+// Karger-Stein Algorithm.
+// https://en.wikipedia.org/wiki/Karger%27s_algorithm#Karger%E2%80%93Stein_algorithm
+// Returns the side of each vertex of a small cut, and the cut size.
+// Recursing twice on each contraction to n/sqrt(2) vertices finds a minimum cut with
+// probability Omega(1/log n), compared to O(1/n^2) for plain Karger.
+fn karger_stein(
+    num_vertices: usize,
+    edges: &Vec<(usize, usize, usize)>,
+    rng: &mut StdRng,
+) -> (Vec<bool>, usize) {
+    if num_vertices <= 6 {
+        let (_, cut_edges, mapping) = contract(num_vertices, edges, 2, rng);
+        let cut_size = cut_edges.iter().map(|(_, _, w)| w).sum();
+        return (mapping.iter().map(|v| *v == 0).collect(), cut_size);
+    }
+    let target = 1 + (num_vertices as f64 / 2f64.sqrt()).ceil() as usize;
+    let mut best: Option<(Vec<bool>, usize)> = None;
+    for _ in 0..2 {
+        let (num_contracted, contracted_edges, mapping) =
+            contract(num_vertices, edges, target, rng);
+        let (sides, cut_size) = karger_stein(num_contracted, &contracted_edges, rng);
+        if !matches!(&best, Some((_, best_size)) if *best_size <= cut_size) {
+            best = Some((mapping.iter().map(|v| sides[*v]).collect(), cut_size));
         }
-        let i = ns.len();
-        ns.push(s);
-        is.insert(s, i);
-        nbrs.push(Vec::new());
-        return i;
     }
+    return best.unwrap();
+}
 
-    for (name, adj) in connections {
-        let i = get_or_create_id(name, &mut ids, &mut names, &mut neighbors);
-        for nbr_name in adj {
-            let j = get_or_create_id(nbr_name, &mut ids, &mut names, &mut neighbors);
-            edges.insert((i, j));
-            neighbors[i].push(j);
+// Runs |num_trials| seeded Karger-Stein trials and returns the smallest cut found.
+// Unlike Stoer-Wagner, the result is only a minimum cut with high probability, but it is
+// reproducible for a given seed.
+fn karger_stein_min_cut(graph: &Graph, seed: u64, num_trials: usize) -> Cut {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let edges: Vec<(usize, usize, usize)> = graph.edges.iter().map(|(i, j)| (*i, *j, 1)).collect();
+    let mut best: Option<(Vec<bool>, usize)> = None;
+    for _ in 0..num_trials {
+        let (sides, cut_size) = karger_stein(graph.names.len(), &edges, &mut rng);
+        if !matches!(&best, Some((_, best_size)) if *best_size <= cut_size) {
+            best = Some((sides, cut_size));
         }
     }
+    return Cut::from_sides(graph, &best.expect("no trials run").0);
+}
 
-    let answer: usize;
-    let mut num_attempts = 0;
-    loop {
-        num_attempts += 1;
-        let (cut_size, a_size, b_size) = try_min_cut(neighbors.clone(), edges.clone());
-        if cut_size == 3 {
-            answer = a_size * b_size;
-            break;
+fn print_cut_answer(cut: &Cut) -> () {
+    assert!(cut.edges.len() == 3, "expected a minimum cut of 3 wires");
+    let answer = cut.side_a.len() * cut.side_b.len();
+
+    println!("Product of partition sizes: {answer}");
+    println!("Expected puzzle answer:     582692");
+}
+
+pub fn solve_part_1(text: &String) -> () {
+    let graph = build_graph(parse_rows(text));
+    print_cut_answer(&stoer_wagner_min_cut(&graph));
+}
+
+const KARGER_STEIN_SEED: u64 = 2023;
+const KARGER_STEIN_NUM_TRIALS: usize = 3;
+
+// Same as part 1, but with seeded Karger-Stein instead of Stoer-Wagner.
+pub fn solve_part_1_karger_stein(text: &String) -> () {
+    let graph = build_graph(parse_rows(text));
+    print_cut_answer(&karger_stein_min_cut(
+        &graph,
+        KARGER_STEIN_SEED,
+        KARGER_STEIN_NUM_TRIALS,
+    ));
+}
+
+// Lists the cut wires and the components on each side of the cut.
+fn format_cut_report(graph: &Graph, cut: &Cut) -> String {
+    let names_of = |vertices: &Vec<usize>| -> String {
+        let mut names: Vec<&str> = vertices.iter().map(|v| graph.names[*v]).collect();
+        names.sort();
+        return names.join(" ");
+    };
+    let mut report = String::new();
+    report += &format!("Cut wires ({}):\n", cut.edges.len());
+    for (i, j) in &cut.edges {
+        report += &format!("  {}/{}\n", graph.names[*i], graph.names[*j]);
+    }
+    report += &format!("Side A ({}): {}\n", cut.side_a.len(), names_of(&cut.side_a));
+    report += &format!("Side B ({}): {}\n", cut.side_b.len(), names_of(&cut.side_b));
+    return report;
+}
+
+// Renders the graph in Graphviz DOT format, with one cluster per side and the cut wires in red.
+fn format_cut_dot(graph: &Graph, cut: &Cut) -> String {
+    let mut dot = String::from("graph wiring {\n");
+    for (cluster, side) in [("a", &cut.side_a), ("b", &cut.side_b)] {
+        dot += &format!("  subgraph cluster_{cluster} {{\n");
+        for v in side {
+            dot += &format!("    {};\n", graph.names[*v]);
         }
+        dot += "  }\n";
     }
+    for (i, j) in &graph.edges {
+        let style = match cut.edges.contains(&(*i, *j)) {
+            true => " [color=red, penwidth=3]",
+            false => "",
+        };
+        dot += &format!("  {} -- {}{};\n", graph.names[*i], graph.names[*j], style);
+    }
+    dot += "}\n";
+    return dot;
+}
 
-    println!("Product of partition sizes: {answer} (after {num_attempts} attempts)");
-    println!("Expected puzzle answer:     582692");
+pub fn print_cut_report(text: &String) -> () {
+    let graph = build_graph(parse_rows(text));
+    let cut = stoer_wagner_min_cut(&graph);
+    print!("{}", format_cut_report(&graph, &cut));
+}
+
+pub fn write_cut_dot(text: &String) -> () {
+    let graph = build_graph(parse_rows(text));
+    let cut = stoer_wagner_min_cut(&graph);
+    let path = "day_25.dot";
+    fs::write(path, format_cut_dot(&graph, &cut)).expect("unable to write DOT file");
+    println!(
+        "Wrote wiring diagram with {} cut wires to {path}",
+        cut.edges.len()
+    );
 }
 
 fn parse_rows(input: &str) -> Vec<(&str, Vec<&str>)> {
@@ -1,71 +1,239 @@
-use std::collections::HashMap;
+use nom::{
+    bytes::complete::tag,
+    character::complete::{space0, space1, u32, u64},
+    combinator::all_consuming,
+    error::Error,
+    multi::separated_list1,
+    sequence::{delimited, separated_pair, tuple},
+};
+
+use num_bigint::BigInt;
+use num_traits::{One, Zero};
+
 use std::collections::HashSet;
 
-// Returns tuple of card number and the number of winning numbers on the card.
-fn score_card(card_string: &String) -> (i32, i32) {
-    let i;
-    match card_string.find(":") {
-        None => {
-            panic!("unparseable line: {}", card_string);
+struct Card {
+    id: u64,
+    winning_numbers: Vec<u32>,
+    numbers: Vec<u32>,
+}
+
+impl Card {
+    // The numbers we have that are winning numbers, in the order we have them.
+    fn matches(&self) -> Vec<u32> {
+        let winning: HashSet<&u32> = self.winning_numbers.iter().collect();
+        return self
+            .numbers
+            .iter()
+            .filter(|n| winning.contains(n))
+            .copied()
+            .collect();
+    }
+
+    // One point for the first match, doubled for each further match.
+    fn points(&self) -> BigInt {
+        return match self.matches().len() {
+            0 => BigInt::zero(),
+            n => BigInt::one() << (n - 1),
+        };
+    }
+}
+
+// Parses a card "Card 1: 41 48 83 86 17 | 83 86  6 31 17  9 48 53".
+fn parse_card(line: &str) -> Card {
+    let numbers = || separated_list1(space1, u32::<_, Error<_>>);
+    match all_consuming(tuple((
+        delimited(tuple((tag("Card"), space1)), u64, tuple((tag(":"), space0))),
+        separated_pair(numbers(), delimited(space1, tag("|"), space1), numbers()),
+    )))(line)
+    {
+        Err(e) => {
+            panic!("bad input: {}", e);
+        }
+        Ok((_, (id, (winning_numbers, numbers)))) => {
+            return Card {
+                id,
+                winning_numbers,
+                numbers,
+            };
+        }
+    }
+}
+
+fn parse_cards(text: &str) -> Vec<Card> {
+    return text.split("\n").map(parse_card).collect();
+}
+
+// What a card does in the cascade, where each instance of a card with n matches wins one copy
+// of each of the next n cards.
+struct CardReport {
+    id: u64,
+    matches: Vec<u32>,
+    points: BigInt,
+    // Indices of the cards that each instance of this card wins a copy of.
+    copies_won: Vec<usize>,
+    // Indices of the cards whose instances win copies of this card.
+    won_from: Vec<usize>,
+    // The original card and all the copies won of it.
+    num_instances: BigInt,
+    // The number of cards one instance of this card ends up producing, itself included.
+    num_cards_produced: BigInt,
+}
+
+// Runs the cascade of copies over the cards, in table order. Copies are only won of cards
+// further down the table, so the number of instances of a card is known once all cards above
+// it are done, and the number of cards a card produces once all cards below it are done.
+// The counts can grow exponentially with the number of cards, hence BigInt.
+fn run_cascade(cards: &Vec<Card>) -> Result<Vec<CardReport>, String> {
+    let mut reports: Vec<CardReport> = Vec::new();
+    for (i, card) in cards.iter().enumerate() {
+        if i > 0 && card.id != cards[i - 1].id + 1 {
+            return Err(format!(
+                "card {} follows card {}, expected consecutive cards",
+                card.id,
+                cards[i - 1].id
+            ));
         }
-        Some(x) => {
-            i = x;
+        let matches = card.matches();
+        if i + matches.len() >= cards.len() {
+            return Err(format!(
+                "card {} wins {} copies, past the end of the table",
+                card.id,
+                matches.len()
+            ));
         }
+        reports.push(CardReport {
+            id: card.id,
+            points: card.points(),
+            copies_won: (i + 1..i + matches.len() + 1).collect(),
+            matches,
+            won_from: Vec::new(),
+            num_instances: BigInt::one(),
+            num_cards_produced: BigInt::one(),
+        });
     }
-    let card_number = card_string[5..i]
-        .trim()
-        .parse::<i32>()
-        .expect("expected an integer");
-    let num_string = card_string[i + 1..].to_string();
-    let mut token_iter = num_string.trim().split(' ').filter(|s| s.len() > 0);
-    let mut winning_numbers: HashSet<i32> = HashSet::new();
-    while let Some(token) = token_iter.next() {
-        if token == "|" {
-            break;
+    for i in 0..reports.len() {
+        for j in reports[i].copies_won.clone() {
+            let num_instances = reports[i].num_instances.clone();
+            reports[j].num_instances += num_instances;
+            reports[j].won_from.push(i);
         }
-        let number = token.parse::<i32>().expect("expected an integer");
-        winning_numbers.insert(number);
     }
-    let mut num_winning = 0;
-    while let Some(token) = token_iter.next() {
-        let number = token.parse::<i32>().expect("expected an integer");
-        if winning_numbers.contains(&number) {
-            num_winning += 1;
+    for i in (0..reports.len()).rev() {
+        for j in reports[i].copies_won.clone() {
+            let num_cards_produced = reports[j].num_cards_produced.clone();
+            reports[i].num_cards_produced += num_cards_produced;
         }
     }
-    return (card_number, num_winning);
+    return Ok(reports);
+}
+
+fn cascade(text: &str) -> Vec<CardReport> {
+    return match run_cascade(&parse_cards(text)) {
+        Ok(reports) => reports,
+        Err(e) => panic!("bad cascade: {e}"),
+    };
+}
+
+fn format_ids(reports: &Vec<CardReport>, indices: &Vec<usize>) -> Vec<String> {
+    return indices.iter().map(|i| reports[*i].id.to_string()).collect();
 }
 
 pub fn solve_part_1(text: &String) -> () {
-    let lines: Vec<String> = text.split("\n").map(|s| s.to_string()).collect();
-    let mut total_points = 0;
-    for line in lines {
-        let (_, num_winning) = score_card(&line);
-        if num_winning > 0 {
-            total_points += 1 << (num_winning - 1);
-        }
-    }
+    let total_points: BigInt = parse_cards(text).iter().map(|card| card.points()).sum();
+
     println!("Card points sum:        {}", total_points);
     println!("Expected puzzle answer: 17803");
 }
 
 pub fn solve_part_2(text: &String) -> () {
-    let mut lines: Vec<String> = text.split("\n").map(|s| s.to_string()).collect();
-    let mut num_cards_generated_from_card: HashMap<i32, i32> = HashMap::new();
-    // By iterating in reverse, all copies generated by a card have already been processed.
-    lines.reverse();
-    let mut num_cards = 0;
-    for line in lines {
-        num_cards += 1;
-        let (card_number, num_winning) = score_card(&line);
-        let mut num_generated = 0;
-        for i in card_number + 1..card_number + num_winning + 1 {
-            num_generated += 1;
-            num_generated += *num_cards_generated_from_card.entry(i).or_default();
-        }
-        num_cards_generated_from_card.insert(card_number, num_generated);
-        num_cards += num_generated;
-    }
+    let num_cards: BigInt = cascade(text).iter().map(|r| &r.num_instances).sum();
+
     println!("Number of cards generated {}", num_cards);
     println!("Expected puzzle answer:   5554894");
 }
+
+// Prints a table of the cascade, one row per card.
+pub fn print_cascade_table(text: &String) -> () {
+    let reports = cascade(text);
+    let rows: Vec<[String; 7]> = reports
+        .iter()
+        .map(|r| {
+            let matches: Vec<String> = r.matches.iter().map(|n| n.to_string()).collect();
+            return [
+                r.id.to_string(),
+                matches.join(" "),
+                r.points.to_string(),
+                format_ids(&reports, &r.copies_won).join(" "),
+                format_ids(&reports, &r.won_from).join(" "),
+                r.num_instances.to_string(),
+                r.num_cards_produced.to_string(),
+            ];
+        })
+        .collect();
+    let header = [
+        "Card",
+        "Matches",
+        "Points",
+        "Copies won of",
+        "Won from",
+        "Instances",
+        "Cards produced",
+    ]
+    .map(|h| h.to_string());
+    let mut widths: Vec<usize> = header.iter().map(|h| h.len()).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    let print_row = |cells: &[String]| {
+        let padded: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:width$}"))
+            .collect();
+        println!("{}", padded.join(" | ").trim_end());
+    };
+    print_row(&header);
+    print_row(
+        &widths
+            .iter()
+            .map(|w| "-".repeat(*w))
+            .collect::<Vec<String>>(),
+    );
+    for row in &rows {
+        print_row(row);
+    }
+}
+
+// Prints the cascade as JSON, one object per card. The counts are written as plain JSON
+// numbers, which have no size limit, though readers may not parse large ones exactly.
+pub fn print_cascade_json(text: &String) -> () {
+    let reports = cascade(text);
+    let json_list = |items: Vec<String>| format!("[{}]", items.join(", "));
+    println!("[");
+    for (i, r) in reports.iter().enumerate() {
+        let fields = [
+            format!("\"card\": {}", r.id),
+            format!(
+                "\"matches\": {}",
+                json_list(r.matches.iter().map(|n| n.to_string()).collect())
+            ),
+            format!("\"points\": {}", r.points),
+            format!(
+                "\"copies_won_of\": {}",
+                json_list(format_ids(&reports, &r.copies_won))
+            ),
+            format!(
+                "\"won_from\": {}",
+                json_list(format_ids(&reports, &r.won_from))
+            ),
+            format!("\"instances\": {}", r.num_instances),
+            format!("\"cards_produced\": {}", r.num_cards_produced),
+        ];
+        let separator = if i + 1 < reports.len() { "," } else { "" };
+        println!("  {{{}}}{separator}", fields.join(", "));
+    }
+    println!("]");
+}
@@ -1,5 +1,4 @@
 use std::cmp;
-use std::collections::VecDeque;
 
 // Returns a pair of:
 // - a vector of seed tokens, interpreted differently for part 1 and 2.
@@ -54,92 +53,264 @@ fn parse_input(text: &String) -> (Vec<i64>, Vec<Vec<(i64, i64, i64)>>) {
     return (ids, ranges_lists);
 }
 
-pub fn solve_part_1(text: &String) -> () {
-    let (mut ids, ranges_lists) = parse_input(text);
-    for ranges in ranges_lists.iter() {
-        for id in &mut ids {
-            for (t, s, l) in ranges.iter() {
-                if *id >= *s && *id < s + l {
-                    *id += t - s;
-                    break;
+// A map of integers that shifts the integers in some intervals by an offset per interval.
+// Stored as sorted breakpoints (start, offset), each piece reaching up to the next start, or
+// i64::MAX for the last piece. The first piece starts at i64::MIN, and the unbounded pieces
+// have offset 0, as integers outside all intervals map to themselves. The image of each piece
+// fits in i64, which composing maps checks.
+#[derive(Clone)]
+struct PiecewiseShift {
+    pieces: Vec<(i64, i64)>,
+}
+
+impl PiecewiseShift {
+    fn identity() -> PiecewiseShift {
+        return PiecewiseShift {
+            pieces: vec![(i64::MIN, 0)],
+        };
+    }
+
+    // Builds a map from almanac entries (target range start, source range start, range length).
+    fn from_entries(entries: &Vec<(i64, i64, i64)>) -> Result<PiecewiseShift, String> {
+        let mut intervals: Vec<(i64, i64, i64, usize)> = Vec::new();
+        for (i, (t, s, l)) in entries.iter().enumerate() {
+            if *l < 0 {
+                return Err(format!("entry {} has negative length {l}", i + 1));
+            }
+            if *l == 0 {
+                continue;
+            }
+            match (s.checked_add(*l), t.checked_add(*l), t.checked_sub(*s)) {
+                (Some(end), Some(_), Some(offset)) if end < i64::MAX => {
+                    intervals.push((*s, end, offset, i))
                 }
+                _ => return Err(format!("entry {} is out of range", i + 1)),
+            }
+        }
+        intervals.sort_unstable();
+        for pair in intervals.windows(2) {
+            let ((_, end, _, i), (start, _, _, j)) = (pair[0], pair[1]);
+            if start < end {
+                return Err(format!(
+                    "entries {} and {} have overlapping source ranges",
+                    cmp::min(i, j) + 1,
+                    cmp::max(i, j) + 1
+                ));
             }
         }
+        let mut pieces: Vec<(i64, i64)> = vec![(i64::MIN, 0)];
+        for (start, end, offset, _) in intervals {
+            pieces.push((start, offset));
+            pieces.push((end, 0));
+        }
+        return Ok(PiecewiseShift::normalized(pieces));
     }
-    let smallest_id = ids.iter().min().expect("seed list must not be empty");
 
-    println!("Smallest seed ID:       {}", smallest_id);
-    println!("Expected puzzle answer: 382895070");
-}
+    // Drops empty pieces and merges neighboring pieces with the same offset.
+    fn normalized(pieces: Vec<(i64, i64)>) -> PiecewiseShift {
+        let mut merged: Vec<(i64, i64)> = Vec::new();
+        for (start, offset) in pieces {
+            if let Some(last) = merged.last_mut() {
+                if last.0 == start {
+                    // The previous piece is empty.
+                    *last = (start, offset);
+                    let len = merged.len();
+                    if len > 1 && merged[len - 2].1 == offset {
+                        merged.pop();
+                    }
+                    continue;
+                }
+                if last.1 == offset {
+                    continue;
+                }
+            }
+            merged.push((start, offset));
+        }
+        return PiecewiseShift { pieces: merged };
+    }
 
-fn are_overlapping((s1, l1): (i64, i64), (s2, l2): (i64, i64)) -> bool {
-    if l1 == 0 || l2 == 0 {
-        return false;
+    fn piece_end(&self, i: usize) -> i64 {
+        return match self.pieces.get(i + 1) {
+            Some((start, _)) => *start,
+            None => i64::MAX,
+        };
     }
-    // If they do not overlap, then range 1 is either fully below or above range 2.
-    let s1_below = s1 + l1 - 1 < s2;
-    let s1_above = s1 > s2 + l2 - 1;
-    return !s1_below && !s1_above;
-}
 
-pub fn solve_part_2(text: &String) -> () {
-    let (ids, ranges_lists) = parse_input(text);
+    // The interval [start, end) that piece |i| is mapped to.
+    fn piece_image(&self, i: usize) -> Result<(i64, i64), String> {
+        let (start, offset) = self.pieces[i];
+        let end = self.piece_end(i);
+        return match (start.checked_add(offset), end.checked_add(offset)) {
+            (Some(image_start), Some(image_end)) => Ok((image_start, image_end)),
+            _ => Err(format!("[{start}, {end}) shifted by {offset} overflows")),
+        };
+    }
 
-    // Parse the seed list into ranges of IDs.
-    let mut id_ranges: VecDeque<(i64, i64)> = VecDeque::new();
-    for i in (0..ids.len()).step_by(2) {
-        id_ranges.push_back((ids[i], ids[i + 1]));
-    }
-
-    // Iterate over the queue of ID ranges.
-    // If a range in the queue overlaps a range in the mapping, map the overlapping part into the next type.
-    // Any non-overlaps are reinserted into the current queue.
-    // If nothing matches, the entire range maps as-is into the next type.
-    let mut new_ranges: VecDeque<(i64, i64)> = VecDeque::new();
-    for map in ranges_lists {
-        while let Some((start, length)) = id_ranges.pop_front() {
-            let mut mapped = false;
-            for (t_start, s_start, r_length) in &map {
-                if are_overlapping((start, length), (*s_start, *r_length)) {
-                    // Split into overlap and non-overlaps.
-                    if start < *s_start {
-                        // There is a non-overlap below the target range.
-                        id_ranges.push_back((start, s_start - start));
-                    }
-                    let end = start + length - 1;
-                    let s_end = s_start + r_length - 1;
-                    if end > s_end {
-                        // There is a non-overlap above the target range.
-                        id_ranges.push_back((s_end + 1, end - s_end - 1));
-                    }
-                    let overlap_start = cmp::max(start, *s_start);
-                    let overlap_end = cmp::min(end, s_end);
-                    let overlap_range_mapped = (
-                        t_start - s_start + overlap_start,
-                        overlap_end - overlap_start + 1,
-                    );
-                    new_ranges.push_back(overlap_range_mapped);
-                    mapped = true;
-                    break;
-                }
+    // The index of the piece containing |x|, in O(log n).
+    fn piece_index(&self, x: i64) -> usize {
+        return self.pieces.partition_point(|(start, _)| *start <= x) - 1;
+    }
+
+    fn apply(&self, x: i64) -> i64 {
+        return x + self.pieces[self.piece_index(x)].1;
+    }
+
+    // Calls |f| with each piece overlapping [start, end), cut to the overlap, with its offset.
+    fn for_each_piece_in(&self, start: i64, end: i64, mut f: impl FnMut(i64, i64, i64) -> ()) {
+        let mut i = self.piece_index(start);
+        while i < self.pieces.len() && self.pieces[i].0 < end {
+            let piece_start = cmp::max(self.pieces[i].0, start);
+            let piece_end = cmp::min(self.piece_end(i), end);
+            f(piece_start, piece_end, self.pieces[i].1);
+            i += 1;
+        }
+    }
+
+    // Maps a set of half-open intervals [start, end), and returns the image as sorted disjoint
+    // intervals. Runs in O(k log n) for k intervals, plus the number of pieces they overlap.
+    fn apply_intervals(&self, intervals: &Vec<(i64, i64)>) -> Vec<(i64, i64)> {
+        let mut image: Vec<(i64, i64)> = Vec::new();
+        for (start, end) in intervals {
+            self.for_each_piece_in(*start, *end, |s, e, offset| {
+                image.push((s + offset, e + offset))
+            });
+        }
+        image.sort_unstable();
+        let mut merged: Vec<(i64, i64)> = Vec::new();
+        for (start, end) in image {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 => last.1 = cmp::max(last.1, end),
+                _ => merged.push((start, end)),
             }
-            if mapped {
-                continue;
+        }
+        return merged;
+    }
+
+    // The map x -> next(self(x)), or an error if a shift of the result does not fit in i64.
+    fn then(&self, next: &PiecewiseShift) -> Result<PiecewiseShift, String> {
+        // The pieces, with None for an offset that overflows.
+        let mut pieces: Vec<(i64, Option<i64>)> = Vec::new();
+        for i in 0..self.pieces.len() {
+            let offset = self.pieces[i].1;
+            let (image_start, image_end) = self.piece_image(i)?;
+            next.for_each_piece_in(image_start, image_end, |s, _, next_offset| {
+                pieces.push((s - offset, offset.checked_add(next_offset)))
+            });
+        }
+        let mut checked_pieces: Vec<(i64, i64)> = Vec::new();
+        for (start, offset) in pieces {
+            match offset {
+                Some(offset) => checked_pieces.push((start, offset)),
+                None => return Err(format!("the shift of {start} overflows")),
+            }
+        }
+        return Ok(PiecewiseShift::normalized(checked_pieces));
+    }
+
+    // The inverse map, if the map is a bijection, i.e. if the shifted pieces do not collide.
+    fn inverse(&self) -> Result<PiecewiseShift, String> {
+        let mut images: Vec<(i64, i64, i64)> = Vec::new();
+        for i in 0..self.pieces.len() {
+            let offset = self.pieces[i].1;
+            let (image_start, image_end) = self.piece_image(i)?;
+            match offset.checked_neg() {
+                Some(inverse_offset) => images.push((image_start, image_end, inverse_offset)),
+                None => return Err(format!("not invertible, as the shift {offset} overflows")),
+            }
+        }
+        images.sort_unstable();
+        for pair in images.windows(2) {
+            if pair[0].1 != pair[1].0 {
+                let (start, end) = match pair[0].1 < pair[1].0 {
+                    true => (pair[0].1, pair[1].0),
+                    false => (pair[1].0, pair[0].1),
+                };
+                return Err(format!(
+                    "not invertible, as [{start}, {end}) is mapped to by {} integers",
+                    match pair[0].1 < pair[1].0 {
+                        true => "no",
+                        false => "several",
+                    }
+                ));
             }
-            // No match - the whole range maps 1:1 to the new type.
-            new_ranges.push_back((start, length));
         }
-        // Prepare the next loop iteration.
-        id_ranges = new_ranges;
-        new_ranges = VecDeque::new();
+        return Ok(PiecewiseShift::normalized(
+            images
+                .iter()
+                .map(|(start, _, offset)| (*start, *offset))
+                .collect(),
+        ));
     }
+}
 
-    let smallest_id = id_ranges
+// Composes the layers into one seed-to-location map.
+fn compose_layers(layers: &Vec<Vec<(i64, i64, i64)>>) -> PiecewiseShift {
+    let mut composed = PiecewiseShift::identity();
+    for (i, entries) in layers.iter().enumerate() {
+        match PiecewiseShift::from_entries(entries).and_then(|layer| composed.then(&layer)) {
+            Ok(result) => composed = result,
+            Err(e) => panic!("bad map {}: {e}", i + 1),
+        }
+    }
+    return composed;
+}
+
+pub fn solve_part_1(text: &String) -> () {
+    let (ids, ranges_lists) = parse_input(text);
+    let seed_to_location = compose_layers(&ranges_lists);
+    let smallest_id = ids
         .iter()
-        .min_by_key(|(s, _)| s)
+        .map(|id| seed_to_location.apply(*id))
+        .min()
+        .expect("seed list must not be empty");
+
+    println!("Smallest seed ID:       {}", smallest_id);
+    println!("Expected puzzle answer: 382895070");
+}
+
+// Parses the seed list into half-open ranges of IDs.
+fn seed_ranges(ids: &Vec<i64>) -> Vec<(i64, i64)> {
+    return ids
+        .chunks(2)
+        .map(|pair| (pair[0], pair[0] + pair[1]))
+        .collect();
+}
+
+pub fn solve_part_2(text: &String) -> () {
+    let (ids, ranges_lists) = parse_input(text);
+    let seed_to_location = compose_layers(&ranges_lists);
+
+    let smallest_id = seed_to_location
+        .apply_intervals(&seed_ranges(&ids))
+        .first()
         .expect("ID list must not be empty")
         .0;
 
     println!("Smallest seed ID:       {}", smallest_id);
     println!("Expected puzzle answer: 17729182");
 }
+
+// Prints the composed seed-to-location map, and maps the smallest location of part 2 back to
+// its seed with the inverse map.
+pub fn print_composed_map(text: &String) -> () {
+    let (ids, ranges_lists) = parse_input(text);
+    let seed_to_location = compose_layers(&ranges_lists);
+    for i in 1..seed_to_location.pieces.len() - 1 {
+        let start = seed_to_location.pieces[i].0;
+        let end = seed_to_location.piece_end(i);
+        match seed_to_location.piece_image(i) {
+            Ok((image_start, image_end)) => {
+                println!("Seeds [{start}, {end}) -> locations [{image_start}, {image_end})")
+            }
+            Err(e) => println!("Seeds {e}"),
+        }
+    }
+    let smallest_location = seed_to_location.apply_intervals(&seed_ranges(&ids))[0].0;
+    match seed_to_location.inverse() {
+        Ok(location_to_seed) => println!(
+            "Smallest location {smallest_location} is reached from seed {}",
+            location_to_seed.apply(smallest_location)
+        ),
+        Err(e) => println!("Seed-to-location map is {e}"),
+    }
+}
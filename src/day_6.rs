@@ -1,42 +1,77 @@
 use nom::{
     bytes::complete::tag,
-    character::complete::{i64, space1},
+    character::complete::{digit1, space1},
     combinator::all_consuming,
     error::Error,
     multi::many1,
     sequence::preceded,
 };
 
+use num_bigint::BigInt;
+use num_traits::{One, Zero};
+
 use std::iter::zip;
 
-fn parse_row(input: &str, tag_str: &str) -> Vec<i64> {
+// Reads the numbers in a row as digit strings, so that they can be of any size.
+fn parse_row<'a>(input: &'a str, tag_str: &str) -> Vec<&'a str> {
     match all_consuming(preceded(
         tag(tag_str),
-        many1(preceded(space1::<_, Error<_>>, i64)),
+        many1(preceded(space1::<_, Error<_>>, digit1)),
     ))(input)
     {
         Err(e) => {
             panic!("bad input: {}", e);
         }
-        Ok((_, times)) => {
-            return times;
+        Ok((_, numbers)) => {
+            return numbers;
         }
     }
 }
 
+fn parse_number(digits: &str) -> BigInt {
+    return digits.parse::<BigInt>().expect("expected integer");
+}
+
+// The hold times that beat the record distance, as an inclusive interval, if any.
+// Holding for h out of |time| milliseconds covers h * (time - h), so the winning hold times are
+// strictly between the roots (time +- sqrt(time^2 - 4 * distance)) / 2. The integer square
+// root gives the lowest winning hold time up to a step, which is then corrected exactly; the
+// highest is symmetric. The race is won at all iff holding for half the time wins, which also
+// bounds the correction.
+fn winning_hold_times(time: &BigInt, distance: &BigInt) -> Option<(BigInt, BigInt)> {
+    let wins = |h: &BigInt| h * (time - h) > *distance;
+    let discriminant: BigInt = time * time - 4 * distance;
+    if discriminant < BigInt::zero() || *time < BigInt::zero() || !wins(&(time / 2)) {
+        return None;
+    }
+    let mut lowest = (time - discriminant.sqrt()) / 2;
+    if lowest < BigInt::zero() {
+        lowest = BigInt::zero();
+    }
+    while !wins(&lowest) {
+        lowest += 1;
+    }
+    while lowest > BigInt::zero() && wins(&(&lowest - 1)) {
+        lowest -= 1;
+    }
+    let highest = time - &lowest;
+    return Some((lowest, highest));
+}
+
+fn num_winning_hold_times(time: &BigInt, distance: &BigInt) -> BigInt {
+    return match winning_hold_times(time, distance) {
+        Some((lowest, highest)) => highest - lowest + 1,
+        None => BigInt::zero(),
+    };
+}
+
 pub fn solve_part_1(text: &String) -> () {
     let lines: Vec<String> = text.split("\n").map(|s| s.to_string()).collect();
     let times = parse_row(&lines[0], "Time:");
     let distances = parse_row(&lines[1], "Distance:");
-    let mut time_product: i64 = 1;
+    let mut time_product = BigInt::one();
     for (time, distance) in zip(times.iter(), distances.iter()) {
-        for t in 1..*time {
-            if t * (*time - t) > *distance {
-                // This is the lowest time that beats the record.
-                time_product *= *time - 2 * t + 1;
-                break;
-            }
-        }
+        time_product *= num_winning_hold_times(&parse_number(time), &parse_number(distance));
     }
 
     println!("Product of number of winning times: {}", time_product);
@@ -44,30 +79,65 @@ pub fn solve_part_1(text: &String) -> () {
 }
 
 pub fn solve_part_2(text: &String) -> () {
+    let lines: Vec<String> = text.split("\n").map(|s| s.to_string()).collect();
+    // De-kern the numbers, which may then be far too large for any fixed-size integer.
+    let time = parse_number(&parse_row(&lines[0], "Time:").join(""));
+    let distance = parse_number(&parse_row(&lines[1], "Distance:").join(""));
+
+    let num_winning_times = num_winning_hold_times(&time, &distance);
+
+    println!("Number of winning times: {}", num_winning_times);
+    println!("Expected puzzle answer:  20048741");
+}
+
+// Prints the winning hold times of each race.
+pub fn print_winning_intervals(text: &String) -> () {
     let lines: Vec<String> = text.split("\n").map(|s| s.to_string()).collect();
     let times = parse_row(&lines[0], "Time:");
-    let time = (times.into_iter().map(|x| x.to_string()))
-        .collect::<Vec<String>>()
-        .join("")
-        .parse::<i64>()
-        .expect("de-kerning failed");
     let distances = parse_row(&lines[1], "Distance:");
-    let distance: i64 = (distances.into_iter().map(|x| x.to_string()))
-        .collect::<Vec<String>>()
-        .join("")
-        .parse::<i64>()
-        .expect("de-kerning failed");
-
-    let mut num_winning_times: i64 = 0;
-    // This could be a binary search but linear search runs really fast anyway.
-    for t in 1..time {
-        if t * (time - t) > distance {
-            // This is the lowest time that beats the record.
-            num_winning_times = time - 2 * t + 1;
-            break;
+    for (time, distance) in zip(times.iter(), distances.iter()) {
+        match winning_hold_times(&parse_number(time), &parse_number(distance)) {
+            Some((lowest, highest)) => println!(
+                "Time {time}, distance {distance}: hold {lowest} to {highest} ms, {} ways",
+                &highest - &lowest + 1
+            ),
+            None => println!("Time {time}, distance {distance}: cannot win"),
         }
     }
+}
 
-    println!("Number of winning times: {}", num_winning_times);
-    println!("Expected puzzle answer:  20048741");
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn brute_force_winning_hold_times(time: i64, distance: i64) -> Option<(i64, i64)> {
+        let winning: Vec<i64> = (0..time + 1)
+            .filter(|h| h * (time - h) > distance)
+            .collect();
+        return Some((*winning.first()?, *winning.last()?));
+    }
+
+    // Every distance that can be beaten, and a few that cannot.
+    #[test]
+    fn winning_hold_times_match_brute_force() {
+        for time in 0..201 {
+            for distance in -2..time * time / 4 + 3 {
+                let expected = brute_force_winning_hold_times(time, distance)
+                    .map(|(lo, hi)| (BigInt::from(lo), BigInt::from(hi)));
+                let actual = winning_hold_times(&BigInt::from(time), &BigInt::from(distance));
+                assert_eq!(actual, expected, "time {time}, distance {distance}");
+            }
+        }
+        // A de-kerned race whose record is the farthest reachable distance.
+        let time = parse_number("1000000000000001");
+        let record = parse_number("250000000000000500000000000000");
+        assert_eq!(winning_hold_times(&time, &record), None);
+        assert_eq!(
+            winning_hold_times(&time, &(&record - 1)),
+            Some((
+                parse_number("500000000000000"),
+                parse_number("500000000000001")
+            ))
+        );
+    }
 }
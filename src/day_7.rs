@@ -5,107 +5,297 @@ use nom::{
     sequence::separated_pair,
 };
 
-use std::cmp;
-use std::collections::HashMap;
-use std::collections::HashSet;
-use std::collections::VecDeque;
-
-fn compute_type_value(hand: &str) -> u64 {
-    let values_set: HashSet<char> = HashSet::from_iter(hand.chars());
-    let mut value_counter: HashMap<char, u64> = HashMap::new();
-    for c in hand.chars() {
-        value_counter
-            .entry(c)
-            .and_modify(|e| *e = *e + 1)
-            .or_insert(1);
-    }
-    let mut type_value = 0;
-    if values_set.len() == 1 {
-        // Five of a kind.
-        type_value = 7;
-    } else if values_set.len() == 2 {
-        let mut four_of_a_kind = false;
-        for c in value_counter.keys() {
-            if *value_counter.get(c).expect("value missing from counters") == 4 {
-                four_of_a_kind = true;
-                // Four of a kind.
-                type_value = 6;
-                break;
+use std::cmp::Ordering;
+use std::fmt;
+
+#[derive(Clone, Copy, Eq, Hash, PartialEq)]
+struct Card {
+    rank: char,
+    // Camel cards have no suits, but other variants may.
+    suit: Option<char>,
+}
+
+struct Hand {
+    cards: Vec<Card>,
+}
+
+impl fmt::Display for Hand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for card in &self.cards {
+            write!(f, "{}", card.rank)?;
+            if let Some(suit) = card.suit {
+                write!(f, "{suit}")?;
             }
         }
-        if !four_of_a_kind {
-            // Full house.
-            type_value = 5;
-        }
-    } else if value_counter.values().position(|x| *x == 3).is_some() {
-        // Three of a kind.
-        type_value = 4;
-    } else if values_set.len() == 3 {
-        // Two pairs.
-        type_value = 3;
-    } else if values_set.len() == 4 {
-        // One pair.
-        type_value = 2;
-    } else {
-        // High card.
-        type_value = 1;
-    }
-    return type_value;
+        return Ok(());
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum HandType {
+    // Cards grouped by rank, as the group sizes in descending order, e.g. [3, 2] for a full house.
+    Groups(Vec<usize>),
+    Straight,
+    Flush,
+    StraightFlush,
 }
 
-fn compute_card_key(hand: &str) -> u64 {
-    assert!(hand.len() == 5);
-    let val_map = vec![
-        '1', '2', '3', '4', '5', '6', '7', '8', '9', 'T', 'J', 'Q', 'K', 'A',
-    ];
-    let values = hand
-        .chars()
-        .map(|c| val_map.iter().position(|k| k == &c))
-        .map(|o| o.expect("char missing from card value map") as u64)
-        .collect::<Vec<u64>>();
-    let mut key: u64 = 0;
-    for v in values {
-        key = 0x10 * key + v;
-    }
-    return key;
+impl HandType {
+    // Hand types are ordered by tier first, and then by group sizes, lexicographically. Without
+    // straights and flushes, this is the plain lexicographic order of the group sizes, which
+    // generalizes the camel cards order to any hand size. Straights and flushes are placed
+    // like in poker.
+    fn strength(&self) -> (u8, &[usize]) {
+        return match self {
+            HandType::Groups(groups) => match groups.as_slice() {
+                [n, ..] if *n >= 5 => (6, groups.as_slice()),
+                [4, ..] => (4, groups.as_slice()),
+                [3, m, ..] if *m >= 2 => (3, groups.as_slice()),
+                _ => (0, groups.as_slice()),
+            },
+            HandType::Straight => (1, &[]),
+            HandType::Flush => (2, &[]),
+            HandType::StraightFlush => (5, &[]),
+        };
+    }
+
+    fn name(&self) -> String {
+        return match self {
+            HandType::Groups(groups) => match groups.as_slice() {
+                [1, ..] => "high card".to_string(),
+                [2, 2, ..] => "two pair".to_string(),
+                [2, ..] => "one pair".to_string(),
+                [3, m, ..] if *m >= 2 => "full house".to_string(),
+                [n, ..] => format!("{n} of a kind"),
+                [] => "empty hand".to_string(),
+            },
+            HandType::Straight => "straight".to_string(),
+            HandType::Flush => "flush".to_string(),
+            HandType::StraightFlush => "straight flush".to_string(),
+        };
+    }
+}
+
+impl Ord for HandType {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.strength().cmp(&other.strength())
+    }
 }
 
-// We assign each hand a unique key in the range 0x0 - 0x7fffff:
-// - the most significant digit is the kind,
-// - the next five hex digits are the value of each card.
-// This allows them to be compared by key.
-fn compute_key(hand: &str) -> u64 {
-    let type_value = compute_type_value(hand);
-    let card_key = compute_card_key(hand);
-    return type_value * 0x100000 + card_key;
+impl PartialOrd for HandType {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
 }
 
-fn compute_max_key_with_joker(hand: &str) -> u64 {
-    let mut type_value = 0;
-    if hand == "JJJJJ" {
-        // Short circuit the all-joker input, to simplify the substitution code.
-        type_value = 7;
-    } else {
-        // Try all combinations of joker substitutions, keeping the one that maximizes type value.
-
-        // Adding a new card value will always yield a lower or equal score to adding a copy of a pre-existing card.
-        // Hence we only need to try substitutions of cards already in the hand.
-        // There is always at least one, since we handle the JJJJJ case above.
-        let non_joker_cards: HashSet<char> = HashSet::from_iter(hand.chars().filter(|c| *c != 'J'));
-        let mut queue: VecDeque<String> = VecDeque::from([String::from(hand)]);
-        while let Some(h) = queue.pop_front() {
-            if h.contains('J') {
-                for sub in non_joker_cards.iter() {
-                    queue.push_back(h.replacen("J", &String::from(*sub), 1));
+// The rules of a camel cards variant.
+struct RuleSet {
+    // Card ranks from weakest to strongest, which breaks ties between hands of the same type.
+    card_order: Vec<char>,
+    // Ranks that act like whatever card makes the hand strongest.
+    wildcards: Vec<char>,
+    hand_size: usize,
+    // A straight is a run of consecutive ranks in |card_order|, not counting wildcards.
+    allow_straights: bool,
+    // A flush is a hand of cards of the same suit.
+    allow_flushes: bool,
+    // Whether each card is written as its rank followed by its suit.
+    suited: bool,
+}
+
+impl RuleSet {
+    fn camel_cards() -> RuleSet {
+        return RuleSet {
+            card_order: "23456789TJQKA".chars().collect(),
+            wildcards: Vec::new(),
+            hand_size: 5,
+            allow_straights: false,
+            allow_flushes: false,
+            suited: false,
+        };
+    }
+
+    // Jokers are wild, and the weakest cards in ties.
+    fn camel_cards_with_jokers() -> RuleSet {
+        return RuleSet {
+            card_order: "J23456789TQKA".chars().collect(),
+            wildcards: vec!['J'],
+            ..RuleSet::camel_cards()
+        };
+    }
+
+    fn parse_hand(&self, text: &str) -> Result<Hand, String> {
+        let chars: Vec<char> = text.chars().collect();
+        let cards: Vec<Card> = match self.suited {
+            true => {
+                if !chars.len().is_multiple_of(2) {
+                    return Err(format!("hand {text} has a card without a suit"));
+                }
+                chars
+                    .chunks(2)
+                    .map(|c| Card {
+                        rank: c[0],
+                        suit: Some(c[1]),
+                    })
+                    .collect()
+            }
+            false => chars
+                .iter()
+                .map(|c| Card {
+                    rank: *c,
+                    suit: None,
+                })
+                .collect(),
+        };
+        if cards.len() != self.hand_size {
+            return Err(format!(
+                "hand {text} has {} cards, expected {}",
+                cards.len(),
+                self.hand_size
+            ));
+        }
+        if let Some(card) = cards.iter().find(|c| !self.card_order.contains(&c.rank)) {
+            return Err(format!("hand {text} has unknown card {}", card.rank));
+        }
+        return Ok(Hand { cards });
+    }
+
+    fn is_wild(&self, card: &Card) -> bool {
+        return self.wildcards.contains(&card.rank);
+    }
+
+    // The non-wild ranks in the hand with their counts, most frequent first, and the number of
+    // wildcards.
+    fn rank_counts(&self, hand: &Hand) -> (Vec<(char, usize)>, usize) {
+        let mut counts: Vec<(char, usize)> = Vec::new();
+        let mut num_wild = 0;
+        for card in &hand.cards {
+            if self.is_wild(card) {
+                num_wild += 1;
+            } else if let Some(entry) = counts.iter_mut().find(|(r, _)| *r == card.rank) {
+                entry.1 += 1;
+            } else {
+                counts.push((card.rank, 1));
+            }
+        }
+        counts.sort_by_key(|(_, n)| std::cmp::Reverse(*n));
+        return (counts, num_wild);
+    }
+
+    // Whether the wildcards can complete a run of consecutive ranks with the other cards.
+    fn can_be_straight(&self, hand: &Hand) -> bool {
+        let straight_order: Vec<char> = self
+            .card_order
+            .iter()
+            .filter(|r| !self.wildcards.contains(r))
+            .copied()
+            .collect();
+        let mut positions: Vec<usize> = hand
+            .cards
+            .iter()
+            .filter(|c| !self.is_wild(c))
+            .map(|c| straight_order.iter().position(|r| *r == c.rank).unwrap())
+            .collect();
+        positions.sort_unstable();
+        let num_non_wild = positions.len();
+        positions.dedup();
+        if positions.len() < num_non_wild || straight_order.len() < self.hand_size {
+            return false;
+        }
+        return match (positions.first(), positions.last()) {
+            (Some(lo), Some(hi)) => hi - lo < self.hand_size,
+            _ => true,
+        };
+    }
+
+    // Whether the wildcards can take the suit of the other cards, all of the same suit.
+    fn can_be_flush(&self, hand: &Hand) -> bool {
+        let mut suits = hand
+            .cards
+            .iter()
+            .filter(|c| !self.is_wild(c))
+            .map(|c| c.suit);
+        return match suits.next() {
+            None => self.suited,
+            Some(None) => false,
+            Some(first) => suits.all(|s| s == first),
+        };
+    }
+
+    // The strongest type the hand can take.
+    // Wildcards best join the largest group of the other cards: a larger largest group always
+    // wins, and adding to a smaller group instead never makes the largest one larger.
+    fn classify(&self, hand: &Hand) -> HandType {
+        let (counts, num_wild) = self.rank_counts(hand);
+        let mut groups: Vec<usize> = counts.iter().map(|(_, n)| *n).collect();
+        match groups.first_mut() {
+            Some(largest) => *largest += num_wild,
+            None => groups.push(num_wild),
+        }
+        let is_straight = self.allow_straights && self.can_be_straight(hand);
+        let is_flush = self.allow_flushes && self.can_be_flush(hand);
+        let mut candidates = vec![HandType::Groups(groups)];
+        if is_straight {
+            candidates.push(HandType::Straight);
+        }
+        if is_flush {
+            candidates.push(HandType::Flush);
+        }
+        if is_straight && is_flush {
+            candidates.push(HandType::StraightFlush);
+        }
+        return candidates.into_iter().max().unwrap();
+    }
+
+    // Describes how the hand is classified, e.g. "KTJJT: 4 of a kind (groups 4-1), wildcards
+    // count as T".
+    fn explain(&self, hand: &Hand) -> String {
+        let hand_type = self.classify(hand);
+        let mut explanation = format!("{hand}: {}", hand_type.name());
+        if let HandType::Groups(groups) = &hand_type {
+            let sizes: Vec<String> = groups.iter().map(|n| n.to_string()).collect();
+            explanation += &format!(" (groups {})", sizes.join("-"));
+        }
+        let (counts, num_wild) = self.rank_counts(hand);
+        if num_wild > 0 {
+            explanation += match (&hand_type, counts.first()) {
+                (HandType::Groups(_), Some((rank, _))) => format!(", wildcards count as {rank}"),
+                (HandType::Groups(_), None) => ", all wildcards".to_string(),
+                (HandType::Straight, _) => ", wildcards fill the straight".to_string(),
+                (HandType::Flush, _) => ", wildcards take the suit".to_string(),
+                (HandType::StraightFlush, _) => {
+                    ", wildcards fill the straight and take the suit".to_string()
                 }
-                continue;
             }
-            // No jokers - ready to evaluate.
-            type_value = cmp::max(type_value, compute_type_value(&h));
+            .as_str();
         }
+        return explanation;
+    }
+
+    // Hands compare by type, and then card by card by rank.
+    fn sort_key(&self, hand: &Hand) -> (HandType, Vec<usize>) {
+        let ranks = hand
+            .cards
+            .iter()
+            .map(|c| self.card_order.iter().position(|r| *r == c.rank).unwrap())
+            .collect();
+        return (self.classify(hand), ranks);
+    }
+
+    fn total_winnings(&self, hands: &Vec<(Hand, u64)>) -> u64 {
+        let mut keys: Vec<((HandType, Vec<usize>), u64)> = hands
+            .iter()
+            .map(|(hand, bid)| (self.sort_key(hand), *bid))
+            .collect();
+        keys.sort();
+
+        let mut total_winnings = 0;
+        for (i, (_, bid)) in keys.iter().enumerate() {
+            let rank = i + 1;
+            total_winnings += (rank as u64) * *bid;
+        }
+        return total_winnings;
     }
-    let card_key = compute_card_key(&hand.replace("J", "1"));
-    return type_value * 0x100000 + card_key;
 }
 
 fn parse_row(input: &str) -> (&str, u64) {
@@ -119,34 +309,56 @@ fn parse_row(input: &str) -> (&str, u64) {
     }
 }
 
+fn parse_hands(text: &str, rules: &RuleSet) -> Vec<(Hand, u64)> {
+    return text
+        .split("\n")
+        .map(parse_row)
+        .map(|(hand, bid)| match rules.parse_hand(hand) {
+            Ok(hand) => (hand, bid),
+            Err(e) => panic!("bad hand: {e}"),
+        })
+        .collect();
+}
+
 pub fn solve_part_1(text: &String) -> () {
-    let lines: Vec<String> = text.split("\n").map(|s| s.to_string()).collect();
-    let mut hands: Vec<(&str, u64)> = lines.iter().map(|s| parse_row(s)).collect();
-    hands.sort_by_key(|(h, _)| compute_key(h));
+    let rules = RuleSet::camel_cards();
+    let total_winnings = rules.total_winnings(&parse_hands(text, &rules));
 
-    let mut total_winnings = 0;
-    for (i, (_, bid)) in hands.iter().enumerate() {
-        let rank = i + 1;
-        total_winnings += (rank as u64) * *bid;
-    }
     println!("Total winnings:         {}", total_winnings);
     println!("Expected puzzle answer: 250370104");
 }
 
 pub fn solve_part_2(text: &String) -> () {
-    let lines: Vec<String> = text.split("\n").map(|s| s.to_string()).collect();
-    let mut hands: Vec<(&str, u64)> = lines.iter().map(|s| parse_row(s)).collect();
-    let mut hand_to_key_map: HashMap<&str, u64> = HashMap::new();
-    for (hand, _) in &hands {
-        hand_to_key_map.insert(hand, compute_max_key_with_joker(&hand));
-    }
-    hands.sort_by_key(|(h, _)| hand_to_key_map.get(h).expect("missing hand"));
+    let rules = RuleSet::camel_cards_with_jokers();
+    let total_winnings = rules.total_winnings(&parse_hands(text, &rules));
 
-    let mut total_winnings = 0;
-    for (i, (_, bid)) in hands.iter().enumerate() {
-        let rank = i + 1;
-        total_winnings += (rank as u64) * *bid;
-    }
     println!("Total winnings:         {}", total_winnings);
     println!("Expected puzzle answer: 251735672");
 }
+
+// Prints how each hand is classified under the rules of both parts, and how allowing
+// straights changes the classifications and the total winnings.
+pub fn print_hand_explanations(text: &String) -> () {
+    for (name, rules) in [
+        ("without jokers", RuleSet::camel_cards()),
+        ("with jokers", RuleSet::camel_cards_with_jokers()),
+    ] {
+        println!("Hands {name}:");
+        for (hand, _) in parse_hands(text, &rules) {
+            println!("  {}", rules.explain(&hand));
+        }
+        let with_straights = RuleSet {
+            allow_straights: true,
+            ..rules
+        };
+        println!("Hands {name}, with straights:");
+        let hands = parse_hands(text, &with_straights);
+        for (hand, _) in &hands {
+            if with_straights.classify(hand) == HandType::Straight {
+                println!("  {}", with_straights.explain(hand));
+            }
+        }
+        let total_winnings = with_straights.total_winnings(&hands);
+        println!("Total winnings {name}, with straights: {total_winnings}");
+    }
+}
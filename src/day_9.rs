@@ -3,6 +3,9 @@ use nom::{
     multi::separated_list1,
 };
 
+use num_bigint::BigInt;
+use num_traits::{One, Zero};
+
 fn parse_list(input: &str) -> Vec<i64> {
     match all_consuming(separated_list1(tag(" "), i64::<_, Error<_>>))(input) {
         Err(e) => {
@@ -14,53 +17,114 @@ fn parse_list(input: &str) -> Vec<i64> {
     }
 }
 
-fn extrapolate_poly(numbers: &Vec<i64>, extrapolate_ending: bool) -> i64 {
-    let mut values: Vec<i64> = numbers.clone();
-    let mut stack: Vec<Vec<i64>> = Vec::new();
-    stack.push(values.clone());
-    while !values.iter().all(|x| *x == 0) {
-        let mut new_values: Vec<i64> = Vec::new();
-        for i in 0..values.len() - 1 {
-            new_values.push(values[i + 1] - values[i]);
+// A sequence f(0), f(1), ... given by its first values, fitted with the polynomial of minimal
+// degree by Newton forward differences: f(x) = sum over k of binomial(x, k) * D^k f(0), where
+// D^k f(0) is the first entry of the k-th row of the difference table.
+// All arithmetic is on BigInt, so evaluating far from the data cannot overflow. The binomials
+// of integer indices are integers, so evaluation is exact without rationals.
+pub struct PolynomialSequence {
+    // D^k f(0) for k = 0..=degree.
+    leading_differences: Vec<BigInt>,
+    // Number of values beyond the degree + 1 needed to determine the polynomial.
+    num_confirmations: usize,
+}
+
+impl PolynomialSequence {
+    // Fits the values with the polynomial of minimal degree, which must be confirmed by at least
+    // |min_confirmations| values beyond the ones that determine it. With no confirmations,
+    // this is the interpolating polynomial, which always exists.
+    pub fn fit(values: &[BigInt], min_confirmations: usize) -> Result<PolynomialSequence, String> {
+        if values.is_empty() {
+            return Err("no values to fit".to_string());
         }
-        stack.push(new_values.clone());
-        values = new_values;
-    }
-    let mut extrapolated_value: i64 = 0;
-    match extrapolate_ending {
-        true => {
-            for &x in stack.iter().map(|v| v.last().unwrap()) {
-                extrapolated_value += x;
-            }
-            return extrapolated_value;
+        let mut row: Vec<BigInt> = values.to_vec();
+        let mut leading_differences: Vec<BigInt> = Vec::new();
+        // The first constant row of the difference table gives the degree.
+        while !row.iter().all(|x| *x == row[0]) {
+            leading_differences.push(row[0].clone());
+            row = row.windows(2).map(|w| &w[1] - &w[0]).collect();
         }
-        false => {
-            for &x in stack.iter().rev().map(|v| v.first().unwrap()) {
-                extrapolated_value = x - extrapolated_value;
-            }
-            return extrapolated_value;
+        leading_differences.push(row[0].clone());
+        let num_confirmations = row.len() - 1;
+        if num_confirmations < min_confirmations {
+            return Err(format!(
+                "not polynomial: the {} values need degree {}, leaving {num_confirmations} of \
+                 {min_confirmations} values to confirm it",
+                values.len(),
+                leading_differences.len() - 1
+            ));
         }
+        return Ok(PolynomialSequence {
+            leading_differences,
+            num_confirmations,
+        });
+    }
+
+    pub fn degree(&self) -> usize {
+        return self.leading_differences.len() - 1;
+    }
+
+    // The value at any integer index, including negative ones.
+    pub fn at(&self, x: &BigInt) -> BigInt {
+        let mut value = BigInt::zero();
+        let mut binomial = BigInt::one();
+        for (k, difference) in self.leading_differences.iter().enumerate() {
+            value += &binomial * difference;
+            // binomial(x, k + 1) = binomial(x, k) * (x - k) / (k + 1), where the division is
+            // exact.
+            binomial = binomial * (x - k) / (k + 1);
+        }
+        return value;
     }
 }
 
-pub fn solve_part_1(text: &String) -> () {
-    let lines: Vec<String> = text.split("\n").map(|s| s.to_string()).collect();
-    let mut sum = 0;
-    for line in lines {
-        let numbers = parse_list(&line);
-        sum += extrapolate_poly(&numbers, /*extrapolate_ending*/ true);
+fn fit_line(line: &str, min_confirmations: usize) -> Result<PolynomialSequence, String> {
+    let values: Vec<BigInt> = parse_list(line).iter().map(|x| BigInt::from(*x)).collect();
+    return PolynomialSequence::fit(&values, min_confirmations);
+}
+
+// Sums the values at the index returned by |index| for each line, given its number of values.
+fn sum_predictions(text: &str, index: impl Fn(usize) -> i64) -> BigInt {
+    let mut sum = BigInt::zero();
+    for line in text.split("\n") {
+        let num_values = line.split(' ').count();
+        // Without confirmations, the fit always succeeds.
+        let sequence = fit_line(line, /*min_confirmations*/ 0).unwrap();
+        sum += sequence.at(&BigInt::from(index(num_values)));
     }
+    return sum;
+}
+
+pub fn solve_part_1(text: &String) -> () {
+    let sum = sum_predictions(text, |num_values| num_values as i64);
     println!("Sum of predictions:     {sum}");
     println!("Expected puzzle answer: 2175229206");
 }
 
 pub fn solve_part_2(text: &String) -> () {
-    let lines: Vec<String> = text.split("\n").map(|s| s.to_string()).collect();
-    let mut sum = 0;
-    for line in lines {
-        let numbers = parse_list(&line);
-        sum += extrapolate_poly(&numbers, /*extrapolate_ending*/ false);
-    }
+    let sum = sum_predictions(text, |_| -1);
     println!("Sum of predictions:     {sum}");
     println!("Expected puzzle answer: 942");
 }
+
+const FAR_FUTURE_INDEX: i64 = 1_000_000_000_000;
+
+// Prints the degree of each sequence with the values before and after it, and far in the
+// future, or that the sequence does not look polynomial.
+pub fn print_sequence_analysis(text: &String) -> () {
+    for line in text.split("\n") {
+        let num_values = line.split(' ').count();
+        match fit_line(line, /*min_confirmations*/ 1) {
+            Err(e) => println!("{line}: {e}"),
+            Ok(sequence) => println!(
+                "{line}: degree {} confirmed by {} values, previous {}, next {}, at index \
+                 {FAR_FUTURE_INDEX}: {}",
+                sequence.degree(),
+                sequence.num_confirmations,
+                sequence.at(&BigInt::from(-1)),
+                sequence.at(&BigInt::from(num_values)),
+                sequence.at(&BigInt::from(FAR_FUTURE_INDEX))
+            ),
+        }
+    }
+}
@@ -25,6 +25,8 @@ mod day_8;
 mod day_9;
 
 mod geometry;
+mod nonogram;
+mod schematic;
 
 use std::env;
 use std::fs;
@@ -35,6 +37,7 @@ struct Config {
     part1: bool,
     part2: bool,
     input_file: String,
+    mode: Option<String>,
 }
 
 // Somewhere out there are at least five well-established libraries that provide flag parsing.
@@ -44,6 +47,7 @@ fn parse_flags() -> Config {
         part1: false,
         part2: false,
         input_file: "input.txt".to_string(),
+        mode: None,
     };
     let args: Vec<String> = env::args().collect();
     for arg in &args[1..] {
@@ -75,6 +79,8 @@ fn parse_flags() -> Config {
             }
         } else if arg.starts_with("--input_file=") {
             config.input_file = arg[13..].to_string();
+        } else if arg.starts_with("--mode=") {
+            config.mode = Some(arg[7..].to_string());
         } else {
             panic!("Unrecognized command line flag: {}", arg);
         }
@@ -131,18 +137,69 @@ fn solutions() -> [Vec<Solution>; 25] {
     ];
 }
 
+// Extra ways to run a day's solution, beyond the two puzzle parts, by (day, mode name).
+fn modes() -> Vec<(usize, &'static str, Solution)> {
+    return vec![
+        (1, "matches", day_1::print_matches),
+        (2, "bag", day_2::print_bag_analysis),
+        (3, "schematic", day_3::print_schematic_report),
+        (4, "table", day_4::print_cascade_table),
+        (4, "json", day_4::print_cascade_json),
+        (5, "composed", day_5::print_composed_map),
+        (6, "intervals", day_6::print_winning_intervals),
+        (7, "explain", day_7::print_hand_explanations),
+        (9, "analyze", day_9::print_sequence_analysis),
+        (12, "arrangements", day_12::print_arrangements),
+        (12, "nonogram", nonogram::solve_and_print),
+        (16, "beams", day_16::print_beams),
+        (16, "best_beams", day_16::print_best_beams),
+        (16, "entries", day_16::print_entry_report),
+        (17, "paths", day_17::print_paths),
+        (19, "trace", day_19::print_part_traces),
+        (19, "analyze", day_19::print_workflow_analysis),
+        (20, "inspect", day_20::print_circuit_inspection),
+        (20, "dot", day_20::write_dot),
+        (20, "dot_counters", day_20::write_dot_with_counters),
+        (22, "settled", day_22::print_settled_bricks),
+        (22, "chain_reactions", day_22::print_chain_reactions),
+        (25, "karger_stein", day_25::solve_part_1_karger_stein),
+        (25, "report", day_25::print_cut_report),
+        (25, "dot", day_25::write_cut_dot),
+    ];
+}
+
 /* Usage:
- * aoc2023 [--days=<n,m,..>] [--part1] [--part2] [--input_file=<path>]
+ * aoc2023 [--days=<n,m,..>] [--part1] [--part2] [--input_file=<path>] [--mode=<name>]
  * Runs all solutions on the correponsing input.txt files in data/.
  *
  * If --days is specified, executes just the given days.
  * If --partN is specified, executes just that part of the problem.
  * If flag --input_file is specified, replaces the default input.txt in data/.
+ * If --mode is specified, runs that extra mode instead of the parts, for the days that have it.
 */
 fn main() {
     let config = parse_flags();
     let solutions = solutions();
+    let modes = modes();
+    if let Some(mode) = &config.mode {
+        if !modes.iter().any(|(_, name, _)| name == mode) {
+            panic!("Unrecognized mode: {}", mode);
+        }
+    }
     for day in config.days {
+        if let Some(mode) = &config.mode {
+            for (_, name, solution) in modes.iter().filter(|(d, n, _)| *d == day && n == mode) {
+                let input_text = read_input_file(
+                    Path::new("data")
+                        .join(format!("day_{}", day))
+                        .join(&config.input_file),
+                );
+                println!(" --- Day {}", day);
+                println!(" ------ Mode {}", name);
+                solution(&input_text);
+            }
+            continue;
+        }
         let input_text = read_input_file(
             Path::new("data")
                 .join(format!("day_{}", day))
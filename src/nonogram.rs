@@ -0,0 +1,259 @@
+use crate::day_12::RowPlacements;
+
+use nom::{
+    bytes::complete::tag,
+    character::complete::{line_ending, u64},
+    combinator::all_consuming,
+    error::Error,
+    multi::{count, separated_list1},
+    sequence::separated_pair,
+};
+
+use std::collections::VecDeque;
+
+// A nonogram: a grid to fill, given the lengths of the runs of filled cells in each row and
+// column. Each line is exactly a row of springs in day 12, with '#' filled, '.' empty and '?'
+// unknown, so the day 12 line solver does the propagation.
+struct Nonogram {
+    row_runs: Vec<Vec<usize>>,
+    col_runs: Vec<Vec<usize>>,
+}
+
+type Grid = Vec<Vec<char>>;
+
+// A row or column of the grid.
+#[derive(Clone, Copy)]
+enum Line {
+    Row(usize),
+    Col(usize),
+}
+
+impl Nonogram {
+    fn line_runs(&self, line: Line) -> &Vec<usize> {
+        return match line {
+            Line::Row(r) => &self.row_runs[r],
+            Line::Col(c) => &self.col_runs[c],
+        };
+    }
+
+    fn line_cells(&self, grid: &Grid, line: Line) -> String {
+        return match line {
+            Line::Row(r) => grid[r].iter().collect(),
+            Line::Col(c) => grid.iter().map(|row| row[c]).collect(),
+        };
+    }
+
+    // Sets the cells forced by the clues until no line forces more, or returns None if some
+    // line has no arrangement left.
+    fn propagate(&self, mut grid: Grid, dirty: Vec<Line>) -> Option<Grid> {
+        let mut queue: VecDeque<Line> = VecDeque::from(dirty);
+        let mut is_queued_row = vec![false; self.row_runs.len()];
+        let mut is_queued_col = vec![false; self.col_runs.len()];
+        for line in &queue {
+            match line {
+                Line::Row(r) => is_queued_row[*r] = true,
+                Line::Col(c) => is_queued_col[*c] = true,
+            }
+        }
+        while let Some(line) = queue.pop_front() {
+            match line {
+                Line::Row(r) => is_queued_row[r] = false,
+                Line::Col(c) => is_queued_col[c] = false,
+            }
+            let cells = self.line_cells(&grid, line);
+            let forced = RowPlacements::new(&cells, self.line_runs(line))
+                .expect("arrangements of each line were counted when parsing")
+                .forced_cells()?;
+            for (i, (old, new)) in cells.chars().zip(forced.chars()).enumerate() {
+                if old == new {
+                    continue;
+                }
+                // A newly forced cell may force more in the crossing line.
+                match line {
+                    Line::Row(r) => {
+                        grid[r][i] = new;
+                        if !is_queued_col[i] {
+                            is_queued_col[i] = true;
+                            queue.push_back(Line::Col(i));
+                        }
+                    }
+                    Line::Col(c) => {
+                        grid[i][c] = new;
+                        if !is_queued_row[i] {
+                            is_queued_row[i] = true;
+                            queue.push_back(Line::Row(i));
+                        }
+                    }
+                }
+            }
+        }
+        return Some(grid);
+    }
+
+    // Tries both values for each unknown cell, and sets the cell to the other value when
+    // propagating one leads to a contradiction, until no more cells are set. This catches
+    // much of what line-by-line propagation misses.
+    // If the grid is not solved then, the outcomes of the cell whose values both decide the
+    // most cells are the branches to search, which keeps the search tree small.
+    fn probe(&self, mut grid: Grid) -> Probe {
+        let num_known = |g: &Grid| g.iter().flatten().filter(|c| **c != '?').count();
+        loop {
+            let mut changed = false;
+            // The outcomes of the best cell to branch on, with the cells both decide.
+            let mut best: Option<(usize, [Grid; 2])> = None;
+            for r in 0..grid.len() {
+                for c in 0..grid[r].len() {
+                    if grid[r][c] != '?' {
+                        continue;
+                    }
+                    let mut outcomes = ['#', '.'].map(|guess| {
+                        let mut guessed = grid.clone();
+                        guessed[r][c] = guess;
+                        return self.propagate(guessed, vec![Line::Row(r), Line::Col(c)]);
+                    });
+                    match outcomes {
+                        [None, None] => return Probe::Contradiction,
+                        [Some(_), None] => grid = outcomes[0].take().unwrap(),
+                        [None, Some(_)] => grid = outcomes[1].take().unwrap(),
+                        [Some(filled), Some(empty)] => {
+                            // Cells that both values force the same way are forced anyway,
+                            // and may force more cells in their lines.
+                            let mut dirty: Vec<Line> = Vec::new();
+                            for (row, (filled_row, empty_row)) in
+                                filled.iter().zip(&empty).enumerate()
+                            {
+                                for (col, (f, e)) in filled_row.iter().zip(empty_row).enumerate() {
+                                    if grid[row][col] == '?' && *f != '?' && f == e {
+                                        grid[row][col] = *f;
+                                        dirty.extend([Line::Row(row), Line::Col(col)]);
+                                    }
+                                }
+                            }
+                            if !dirty.is_empty() {
+                                match self.propagate(grid, dirty) {
+                                    Some(propagated) => grid = propagated,
+                                    None => return Probe::Contradiction,
+                                }
+                            } else {
+                                let score = num_known(&filled).min(num_known(&empty));
+                                if !matches!(&best, Some((best_score, _)) if *best_score >= score) {
+                                    best = Some((score, [filled, empty]));
+                                }
+                                continue;
+                            }
+                        }
+                    }
+                    changed = true;
+                }
+            }
+            if changed {
+                continue;
+            }
+            // Without changes, the outcomes are for the final grid.
+            return match best {
+                Some((_, branches)) => Probe::Branch(branches),
+                None => Probe::Solved(grid),
+            };
+        }
+    }
+
+    // Finds up to |max_solutions| solutions, by propagation and backtracking.
+    fn solve(&self, max_solutions: usize) -> Vec<Grid> {
+        let empty_grid: Grid = vec![vec!['?'; self.col_runs.len()]; self.row_runs.len()];
+        let all_lines: Vec<Line> = (0..self.row_runs.len())
+            .map(Line::Row)
+            .chain((0..self.col_runs.len()).map(Line::Col))
+            .collect();
+        let mut solutions: Vec<Grid> = Vec::new();
+        let mut to_visit: Vec<Grid> = Vec::new();
+        if let Some(grid) = self.propagate(empty_grid, all_lines) {
+            to_visit.push(grid);
+        }
+        while let Some(grid) = to_visit.pop() {
+            match self.probe(grid) {
+                Probe::Contradiction => {}
+                Probe::Solved(grid) => {
+                    solutions.push(grid);
+                    if solutions.len() == max_solutions {
+                        break;
+                    }
+                }
+                Probe::Branch([filled, empty]) => {
+                    // Try filled first.
+                    to_visit.push(empty);
+                    to_visit.push(filled);
+                }
+            }
+        }
+        return solutions;
+    }
+}
+
+enum Probe {
+    Contradiction,
+    Solved(Grid),
+    // The grids after guessing either value of a cell.
+    Branch([Grid; 2]),
+}
+
+// Reads the run lengths of a line, e.g. "3,1", or "0" for an empty line.
+fn parse_runs(runs: Vec<u64>) -> Vec<usize> {
+    return runs
+        .iter()
+        .filter(|r| **r > 0)
+        .map(|r| *r as usize)
+        .collect();
+}
+
+// Parses the row clues, a blank line, and the column clues, each clue on its own line.
+fn parse_nonogram(input: &str) -> Nonogram {
+    let clues = || {
+        separated_list1(
+            line_ending::<_, Error<_>>,
+            separated_list1(tag(","), u64::<_, Error<_>>),
+        )
+    };
+    match all_consuming(separated_pair(clues(), count(line_ending, 2), clues()))(input) {
+        Err(e) => {
+            panic!("bad input: {}", e);
+        }
+        Ok((_, (rows, cols))) => {
+            let nonogram = Nonogram {
+                row_runs: rows.into_iter().map(parse_runs).collect(),
+                col_runs: cols.into_iter().map(parse_runs).collect(),
+            };
+            // A line has the most arrangements while it is still unknown, so if those can be
+            // counted, so can the arrangements of the line in any partially solved grid.
+            let rows = (0..nonogram.row_runs.len()).map(Line::Row);
+            let cols = (0..nonogram.col_runs.len()).map(Line::Col);
+            for line in rows.chain(cols) {
+                let len = match line {
+                    Line::Row(_) => nonogram.col_runs.len(),
+                    Line::Col(_) => nonogram.row_runs.len(),
+                };
+                if let Err(e) = RowPlacements::new(&"?".repeat(len), nonogram.line_runs(line)) {
+                    panic!("bad input: a line has {e}");
+                }
+            }
+            return nonogram;
+        }
+    }
+}
+
+// Solves a nonogram, and prints the picture and whether it is the only solution.
+pub fn solve_and_print(text: &String) -> () {
+    let nonogram = parse_nonogram(text);
+    let solutions = nonogram.solve(/*max_solutions*/ 2);
+    match solutions.first() {
+        None => println!("No solution"),
+        Some(grid) => {
+            for row in grid {
+                println!("{}", row.iter().collect::<String>());
+            }
+            match solutions.len() {
+                1 => println!("The solution is unique"),
+                _ => println!("There are other solutions"),
+            }
+        }
+    }
+}
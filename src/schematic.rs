@@ -0,0 +1,123 @@
+use crate::geometry::Point;
+
+use std::collections::HashMap;
+
+// A run of digits in the schematic, starting at |position| and going right.
+pub struct NumberNode {
+    pub value: u64,
+    pub position: Point,
+    pub len: usize,
+}
+
+// Any character other than a digit or '.'.
+pub struct SymbolNode {
+    pub symbol: char,
+    pub position: Point,
+}
+
+// An engine schematic as a graph, with an edge between each number and each symbol it touches,
+// including diagonally. Nodes are referred to by their index in |numbers| or |symbols|.
+pub struct Schematic {
+    pub numbers: Vec<NumberNode>,
+    pub symbols: Vec<SymbolNode>,
+    symbols_of_number: Vec<Vec<usize>>,
+    numbers_of_symbol: Vec<Vec<usize>>,
+}
+
+impl Schematic {
+    pub fn parse(text: &str) -> Schematic {
+        let mut numbers: Vec<NumberNode> = Vec::new();
+        let mut symbols: Vec<SymbolNode> = Vec::new();
+        for (y, line) in text.split("\n").enumerate() {
+            // The number being read, if the previous character was a digit.
+            let mut current: Option<NumberNode> = None;
+            for (x, c) in line.chars().enumerate() {
+                let position = Point {
+                    x: x as i64,
+                    y: y as i64,
+                };
+                match (c.to_digit(10), &mut current) {
+                    (Some(d), Some(number)) => {
+                        number.value = 10 * number.value + d as u64;
+                        number.len += 1;
+                    }
+                    (Some(d), None) => {
+                        current = Some(NumberNode {
+                            value: d as u64,
+                            position,
+                            len: 1,
+                        });
+                    }
+                    (None, _) => {
+                        numbers.extend(current.take());
+                        if c != '.' {
+                            symbols.push(SymbolNode {
+                                symbol: c,
+                                position,
+                            });
+                        }
+                    }
+                }
+            }
+            numbers.extend(current);
+        }
+
+        let symbol_at: HashMap<Point, usize> = symbols
+            .iter()
+            .enumerate()
+            .map(|(s, symbol)| (symbol.position, s))
+            .collect();
+        let mut symbols_of_number: Vec<Vec<usize>> = vec![Vec::new(); numbers.len()];
+        let mut numbers_of_symbol: Vec<Vec<usize>> = vec![Vec::new(); symbols.len()];
+        for (n, number) in numbers.iter().enumerate() {
+            // The cells around the number, in reading order.
+            for y in number.position.y - 1..number.position.y + 2 {
+                for x in number.position.x - 1..number.position.x + number.len as i64 + 1 {
+                    if let Some(s) = symbol_at.get(&Point { x, y }) {
+                        symbols_of_number[n].push(*s);
+                        numbers_of_symbol[*s].push(n);
+                    }
+                }
+            }
+        }
+        return Schematic {
+            numbers,
+            symbols,
+            symbols_of_number,
+            numbers_of_symbol,
+        };
+    }
+
+    pub fn symbols_touching(&self, number: usize) -> &Vec<usize> {
+        return &self.symbols_of_number[number];
+    }
+
+    pub fn numbers_touching(&self, symbol: usize) -> &Vec<usize> {
+        return &self.numbers_of_symbol[symbol];
+    }
+
+    // The numbers that touch at least one symbol.
+    pub fn part_numbers(&self) -> Vec<usize> {
+        return (0..self.numbers.len())
+            .filter(|n| !self.symbols_of_number[*n].is_empty())
+            .collect();
+    }
+
+    // The |symbol|s that touch exactly |num_numbers| numbers, e.g. the gears are the '*'s that
+    // touch exactly two.
+    pub fn symbols_with(&self, symbol: char, num_numbers: usize) -> Vec<usize> {
+        return (0..self.symbols.len())
+            .filter(|s| {
+                self.symbols[*s].symbol == symbol && self.numbers_of_symbol[*s].len() == num_numbers
+            })
+            .collect();
+    }
+
+    // The numbers that touch more than one symbol, which would be counted more than once by
+    // summing over symbols.
+    pub fn numbers_touching_multiple_symbols(&self) -> Vec<usize> {
+        return (0..self.numbers.len())
+            .filter(|n| self.symbols_of_number[*n].len() > 1)
+            .collect();
+    }
+}